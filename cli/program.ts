@@ -41,6 +41,8 @@ const GLOBAL_STATE_SEED = "global_state";
 const GOVERNANCE_TOKEN_SEED = "governance_token";
 const REWARDS_VAULT_SEED = "rewards_vault";
 const SOL_REWARDS_WALLET_SEED = "sol_rewards_wallet";
+const EMISSION_SCHEDULE_SEED = "emission_schedule";
+const RATE_HISTORY_SEED = "rate_history";
 
 // Add metadata configuration
 const TOKEN_METADATA = {
@@ -169,6 +171,7 @@ async function initializeProgram(
   feesWalletAddress: string,
   totalSupplyArg: string,
   rewardRateArg: string = "5555555",
+  halvingIntervalArg: string = "47250",
   network: string,
   gambleFeeLamportsArg: string = "100000000",
   stakingLockupSlotsArg: string = "432000",
@@ -232,6 +235,7 @@ async function initializeProgram(
     console.log("Initializing program...", totalSupplyArg);
     const TOTAL_SUPPLY = new BN(Number(totalSupplyArg) * 10 ** TOKEN_DECIMALS);
     const REWARD_RATE = new BN(rewardRateArg);
+    const HALVING_INTERVAL = new BN(halvingIntervalArg);
     const COOLDOWN_SLOTS = new BN(0);
     const initialFarmPurchaseFeeLamports = new BN(300_000_000); // 0.3 SOL
     const boosterPackCostMicrotokens = new BN(10_000_000);
@@ -243,6 +247,7 @@ async function initializeProgram(
         currentSlot,
         TOTAL_SUPPLY,
         REWARD_RATE,
+        HALVING_INTERVAL,
         COOLDOWN_SLOTS,
         initialFarmPurchaseFeeLamports,
         boosterPackCostMicrotokens,
@@ -313,6 +318,15 @@ async function updateParameters(
       program.programId
     );
 
+    const [emissionSchedule] = PublicKey.findProgramAddressSync(
+      [Buffer.from(EMISSION_SCHEDULE_SEED), tokenMint.toBuffer()],
+      program.programId
+    );
+    const [rateHistory] = PublicKey.findProgramAddressSync(
+      [Buffer.from(RATE_HISTORY_SEED), tokenMint.toBuffer()],
+      program.programId
+    );
+
     console.log(
       `Updating parameter index ${parameterIndex} to ${parameterValue}...`
     );
@@ -321,6 +335,8 @@ async function updateParameters(
       .accountsStrict({
         authority: wallet.publicKey,
         globalState: globalStateKey,
+        emissionSchedule,
+        rateHistory,
       })
       .rpc();
 
@@ -370,12 +386,23 @@ async function updatePoolManual(
       program.programId
     );
 
+    const [emissionSchedule] = PublicKey.findProgramAddressSync(
+      [Buffer.from(EMISSION_SCHEDULE_SEED), tokenMint.toBuffer()],
+      program.programId
+    );
+    const [rateHistory] = PublicKey.findProgramAddressSync(
+      [Buffer.from(RATE_HISTORY_SEED), tokenMint.toBuffer()],
+      program.programId
+    );
+
     console.log("Updating pool manually...");
     const tx = await program.methods
       .updatePoolManual()
       .accountsStrict({
         authority: wallet.publicKey,
         globalState: globalStateKey,
+        emissionSchedule,
+        rateHistory,
       })
       .rpc();
 
@@ -504,12 +531,23 @@ async function resetPlayer(
       program.programId
     );
 
+    const [emissionSchedule] = PublicKey.findProgramAddressSync(
+      [Buffer.from(EMISSION_SCHEDULE_SEED), tokenMint.toBuffer()],
+      program.programId
+    );
+    const [rateHistory] = PublicKey.findProgramAddressSync(
+      [Buffer.from(RATE_HISTORY_SEED), tokenMint.toBuffer()],
+      program.programId
+    );
+
     console.log("Resetting player...");
     const tx = await program.methods
       .resetPlayer()
       .accountsStrict({
         authority: wallet.publicKey,
         globalState: globalStateKey,
+        emissionSchedule,
+        rateHistory,
         player: playerKey,
         playerWallet: playerWallet,
         tokenMint: tokenMint,
@@ -637,6 +675,11 @@ program
     "-r, --reward-rate <number>",
     "Reward rate (integer, e.g. 50000000)"
   )
+  .option(
+    "--halving-interval <number>",
+    "Slots between reward rate halvings",
+    "47250"
+  )
   .requiredOption("-c, --cooldown-slots <number>", "Cooldown slots", "0")
   .option(
     "-n, --network <url>",
@@ -650,6 +693,7 @@ program
       opts.feesWallet,
       opts.totalSupply,
       opts.rewardRate,
+      opts.halvingInterval,
       opts.network
     );
   });
@@ -657,7 +701,7 @@ program
 program
   .command("update-parameter")
   .description(
-    "Update a single program parameter by index. Indices: 0:ReferralFee, 1:BurnRate, 2:CooldownSlots, 3:DustThresholdDivisor, 4:InitialFarmPurchaseFeeLamports, 5:BoosterPackCostMicrotokens, 6:GambleFeeLamports, 7:StakingLockupSlots, 8:TokenRewardRate, 9:RewardRate"
+    "Update a single program parameter by index. Indices: 0:ReferralFee, 1:BurnRate, 2:CooldownSlots, 3:DustThresholdDivisor, 4:InitialFarmPurchaseFeeLamports, 5:BoosterPackCostMicrotokens, 6:GambleFeeLamports, 7:StakingLockupSlots, 8:TokenRewardRate, 9:RewardRate, 10:HalvingInterval, 11:RewardMultiplierMin, 12:RewardMultiplierMax, 13:RateUpdateCooldownSlots, 14:VestingPercent, 15:VestingDurationSlots, 16:MiningReferralBps, 17:CardTransferFee, 18:MarketplaceFeeBps"
   )
   .requiredOption("-k, --keypair <path>", "Path to keypair file")
   .requiredOption("-m, --mint <address>", "Token mint address")
@@ -3,6 +3,20 @@ pub const PLAYER_SEED: &[u8] = b"player";
 pub const STAKING_VAULT_SEED: &[u8] = b"staking_vault";
 pub const SOL_REWARDS_WALLET_SEED: &[u8] = b"sol_rewards_wallet";
 pub const REWARDS_VAULT_SEED: &[u8] = b"rewards_vault";
+pub const EMISSION_SCHEDULE_SEED: &[u8] = b"emission_schedule";
+pub const REWARD_MULTIPLIER_ROLL_SEED: &[u8] = b"reward_multiplier_roll";
+pub const RATE_HISTORY_SEED: &[u8] = b"rate_history";
+pub const VESTING_SEED: &[u8] = b"vesting";
+pub const REFERRER_SEED: &[u8] = b"referrer";
+pub const REFERRAL_CONFIG_SEED: &[u8] = b"referral_config";
+pub const REFERRAL_CODE_SEED: &[u8] = b"referral_code";
+pub const CARD_CATALOG_SEED: &[u8] = b"card_catalog";
+pub const LISTING_SEED: &[u8] = b"listing";
+pub const SWAP_OFFER_SEED: &[u8] = b"swap_offer";
+pub const CARD_MINT_SEED: &[u8] = b"card_mint";
+pub const CARD_MINT_RECORD_SEED: &[u8] = b"card_mint_record";
+pub const CARD_VAULT_SEED: &[u8] = b"card_vault";
+pub const FARM_CONFIG_SEED: &[u8] = b"farm_config";
 
 // define switchboard program id
 // feature devnet is different from mainnet
@@ -18,6 +32,7 @@ pub const ACC_SCALE: u128 = 1_000_000_000_000; // 1e12
 pub const MIN_RANDOMNESS_DELAY_SLOTS: u64 = 2; // Minimum slots between commit and settle
 pub const MAX_CARDS_PER_PLAYER: u8 = 128; // Maximum cards a player can have
 pub const MAX_STAKED_CARDS_PER_PLAYER: u8 = 25; // Maximum staked cards a player can have
+pub const MAX_STAKING_SEARCH_NODES: u32 = 10_000; // Search budget for rearrange_staking
 
 // Card Rarities (matching TypeScript CardRarity enum)
 pub const COMMON: u8 = 0;
@@ -34,6 +49,7 @@ pub const STARTER_CARD_IDS: [u16; 3] = [179, 175, 147]; // Glowhare, Flitterfrog
 // === Farm configurations (matching farmList from data.ts) =================================================
 // format: (total_cards, berry_capacity, cost_in_microtokens)
 // Note: Converting costs from data.ts to microtokens (multiply by 1_000_000)
+// Only used to seed the FarmConfig account; gameplay reads the config.
 pub const FARM_CONFIGS: [(u8, u64, u64); 11] = [
     (0, 0, 0),                  // Level 0 - Initial state before buying first farm
     (2, 6, 0), // Level 1 - slotQuantity: 2, berryAvailable: 6, cost: 0 (first farm free)
@@ -50,7 +66,8 @@ pub const FARM_CONFIGS: [(u8, u64, u64); 11] = [
 
 // === Card data from pokemonCardList in data.ts ====================================================
 // format: (id, rarity, hashpower, berry_consumption)
-// This is a comprehensive list of all 191 cards from the TypeScript data
+// This is a comprehensive list of all 191 cards from the TypeScript data.
+// Only used to seed the CardCatalog account; gameplay reads the catalog.
 pub const CARD_DATA: [(u16, u8, u16, u8); 191] = [
     (1, MEGA_RARE, 2916, 128),  // Zephyrdrake
     (2, MEGA_RARE, 2916, 128),  // Bloomingo
@@ -245,14 +262,73 @@ pub const CARD_DATA: [(u16, u8, u16, u8); 191] = [
     (191, COMMON, 4, 2),        // Pebblepup (duplicate of 177)
 ];
 
-// Helper function to get card data by ID
-pub fn get_card_by_id(id: u16) -> Option<(u8, u16, u8)> {
-    CARD_DATA
-        .iter()
-        .find(|(card_id, _, _, _)| *card_id == id)
-        .map(|(_, rarity, hashpower, berry_consumption)| (*rarity, *hashpower, *berry_consumption))
-}
-
 /* ─── DYNAMIC REWARDS ──────────────────────────────────────────────────────── */
 pub const REWARD_RATE_MULTIPLIER_SCALE: u64 = 1000;
 pub const REWARD_RATE_UPDATE_COOLDOWN_SLOTS: u64 = 9000; // Approx. 1 hour (9000 slots / 2.5 slots/sec)
+pub const DEFAULT_REWARD_MULTIPLIER_MIN: u32 = 500; // 0.5x
+pub const DEFAULT_REWARD_MULTIPLIER_MAX: u32 = 1500; // 1.5x
+
+/* ─── EMISSION SCHEDULE ────────────────────────────────────────────────────── */
+pub const MAX_EMISSION_SEGMENTS: usize = 16; // Segments an EmissionSchedule can hold
+
+/* ─── RATE HISTORY ─────────────────────────────────────────────────────────── */
+pub const RATE_HISTORY_LEN: usize = 64; // Entries kept in the RateHistory ring buffer
+
+/* ─── IDLE EMISSION POLICY ─────────────────────────────────────────────────── */
+// What happens to emissions while total_hashpower is zero
+pub const IDLE_EMISSION_DROP: u8 = 0; // Emission is never minted (original behaviour)
+pub const IDLE_EMISSION_ROLLOVER: u8 = 1; // Paid to miners in the next non-zero period
+pub const IDLE_EMISSION_TREASURY: u8 = 2; // Swept to the emission treasury token account
+
+/* ─── VESTING ──────────────────────────────────────────────────────────────── */
+pub const DEFAULT_VESTING_DURATION_SLOTS: u64 = 1_512_000; // Approx. 7 days (9000 slots per hour)
+
+/* ─── MINING REFERRALS ─────────────────────────────────────────────────────── */
+pub const MAX_MINING_REFERRAL_BPS: u64 = 5_000; // Referrer share of a mining claim is capped at 50%
+
+/* ─── REFERRAL TIERS ───────────────────────────────────────────────────────── */
+pub const MAX_REFERRAL_LEVELS: usize = 5; // Deepest referral chain that can be paid
+pub const MAX_REFERRAL_TIER_TOTAL_BPS: u64 = 5_000; // All levels together take at most 50% of a fee
+
+/* ─── REFERRAL CODES ───────────────────────────────────────────────────────── */
+pub const MIN_REFERRAL_CODE_LEN: usize = 3;
+pub const MAX_REFERRAL_CODE_LEN: usize = 16; // Also bounds the ReferralCode account size
+
+/* ─── CARD CATALOG ─────────────────────────────────────────────────────────── */
+pub const MAX_CATALOG_CARDS: usize = 256; // Bounds the CardCatalog account size
+pub const CATALOG_CARD_SIZE: usize = 8; // id, rarity, hashpower, berry_consumption, enabled, booster_eligible
+
+/* ─── MARKETPLACE ──────────────────────────────────────────────────────────── */
+pub const DEFAULT_MARKETPLACE_FEE_BPS: u16 = 500; // 5% of each sale
+pub const MAX_MARKETPLACE_FEE_BPS: u64 = 5_000; // Seller always keeps at least half
+
+/* ─── SWAP OFFERS ──────────────────────────────────────────────────────────── */
+pub const MAX_SWAP_CARDS: usize = 5; // Cards offered, and cards wanted, per swap offer
+
+/* ─── CARD VAULT ───────────────────────────────────────────────────────────── */
+pub const CARD_VAULT_PAGE_SIZE: usize = 64; // Cards per CardVault page
+pub const MAX_CARD_VAULT_PAGES: u8 = 8;
+pub const MAX_VAULT_RECYCLE_CARDS: usize = 31; // Keeps PendingRandomAction within its 129-byte payload
+
+/* ─── FARM CONFIG ──────────────────────────────────────────────────────────── */
+pub const MAX_FARM_LEVELS: usize = 32; // Bounds the FarmConfig account size, level 0 included
+pub const FARM_LEVEL_SIZE: usize = 17; // total_cards, berry_capacity, cost
+
+/* ─── CARD LEVELS ──────────────────────────────────────────────────────────── */
+pub const MAX_CARD_LEVEL: usize = 10; // Entries in GlobalState::card_level_curve
+pub const CARD_LEVEL_SIZE: usize = 7; // xp_required, hashpower_bonus_bps, berry_increase
+pub const MAX_CARD_LEVEL_BONUS_BPS: u16 = 10_000; // A single level-up at most doubles hashpower
+
+// format: (cumulative xp_required, hashpower_bonus_bps, berry_increase); 1 XP per staked slot
+pub const DEFAULT_CARD_LEVEL_CURVE: [(u32, u16, u8); MAX_CARD_LEVEL] = [
+    (216_000, 1_000, 0),    // Level 1 - ~1 day staked, +10% hashpower
+    (648_000, 1_000, 0),    // Level 2 - ~3 days
+    (1_512_000, 1_000, 0),  // Level 3 - ~7 days
+    (3_024_000, 1_000, 0),  // Level 4 - ~14 days
+    (6_480_000, 1_000, 1),  // Level 5 - ~30 days, +1 berry
+    (12_960_000, 1_000, 0), // Level 6 - ~60 days
+    (19_440_000, 1_000, 0), // Level 7 - ~90 days
+    (38_880_000, 1_000, 0), // Level 8 - ~180 days
+    (58_320_000, 1_000, 0), // Level 9 - ~270 days
+    (77_760_000, 1_000, 1), // Level 10 - ~360 days, +1 berry
+];
@@ -118,4 +118,134 @@ pub enum PonzimonError {
     CancelTimeoutNotExpired,
     #[msg("Invalid randomness account owner")]
     InvalidRandomnessAccountOwner,
+
+    // Emission schedule errors
+    #[msg("Emission schedule is full")]
+    EmissionScheduleFull,
+    #[msg("Emission segment must start in the future, after the last segment, and end after it starts")]
+    InvalidEmissionSegment,
+
+    // Reward multiplier roll errors
+    #[msg("A reward multiplier roll is already pending")]
+    RollAlreadyPending,
+    #[msg("No reward multiplier roll is pending")]
+    NoRollPending,
+    #[msg("Invalid reward multiplier bounds, min must be <= max and max > 0")]
+    InvalidRewardMultiplierBounds,
+
+    // Supply audit errors
+    #[msg("Supply audit failed: rewards vault does not cover outstanding liabilities")]
+    SupplyAuditMismatch,
+
+    // Idle emission errors
+    #[msg("Invalid idle emission policy")]
+    InvalidIdleEmissionPolicy,
+    #[msg("The treasury policy requires an emission treasury token account")]
+    EmissionTreasuryNotSet,
+    #[msg("No treasury emission to sweep")]
+    NothingToSweep,
+
+    // Claim delegation errors
+    #[msg("Remaining accounts must be (player, claim destination) pairs")]
+    InvalidRemainingAccounts,
+    #[msg("Account is not a player of this program")]
+    InvalidPlayerAccount,
+    #[msg("Token account is not the player's claim destination")]
+    InvalidClaimDestination,
+
+    // Vesting errors
+    #[msg("Vesting is enabled, the player's vesting account is required")]
+    VestingAccountRequired,
+    #[msg("Vesting percent must be between 0 and 100")]
+    InvalidVestingPercent,
+    #[msg("Vesting duration must be greater than zero")]
+    InvalidVestingDuration,
+    #[msg("No vested tokens have unlocked yet")]
+    NoVestedTokens,
+
+    // Referral tier errors
+    #[msg("Referral chain contains a cycle")]
+    ReferralCycleDetected,
+    #[msg("Invalid referral tiers, too many levels or shares above the cap")]
+    InvalidReferralTiers,
+
+    // Referral code errors
+    #[msg("Referral code must be 3-16 characters of lowercase letters, digits or '_'")]
+    InvalidReferralCode,
+    #[msg("Referrer wallet does not match the referral code owner")]
+    ReferralCodeMismatch,
+
+    // Card catalog errors
+    #[msg("Card rarity is out of range")]
+    InvalidCardRarity,
+    #[msg("Card catalog is full")]
+    CardCatalogFull,
+    #[msg("A card with this id is already in the catalog")]
+    DuplicateCatalogCard,
+    #[msg("Card id is not in the catalog")]
+    CatalogCardNotFound,
+
+    // Card serial errors
+    #[msg("Player has no card with this serial")]
+    CardSerialNotFound,
+
+    // Card transfer errors
+    #[msg("Cannot transfer a card to yourself")]
+    CannotTransferToSelf,
+    #[msg("Recipient has not opted in to receiving card transfers")]
+    RecipientNotAcceptingTransfers,
+
+    // Marketplace errors
+    #[msg("Listing price must be greater than zero")]
+    InvalidListingPrice,
+    #[msg("Cannot buy your own listing")]
+    CannotBuyOwnListing,
+    #[msg("Marketplace fee cannot exceed 50%")]
+    InvalidMarketplaceFee,
+
+    // Swap offer errors
+    #[msg("The same card serial was given more than once")]
+    DuplicateCardSerials,
+    #[msg("Swap offers take 1-5 cards and must ask for cards or tokens")]
+    InvalidSwapOffer,
+    #[msg("Cards given do not match what the swap offer wants")]
+    SwapTermsNotMet,
+    #[msg("Token accounts are required when the swap offer asks for tokens")]
+    SwapTokenAccountsMissing,
+    #[msg("Cannot accept your own swap offer")]
+    CannotAcceptOwnSwap,
+
+    // Card vault errors
+    #[msg("Card vault page is out of range")]
+    InvalidVaultPage,
+    #[msg("Card vault page is full")]
+    CardVaultFull,
+    #[msg("The card vault page holding the pending recycle is required")]
+    CardVaultRequired,
+
+    // Batch staking errors
+    #[msg("At least one card index is required")]
+    InvalidCardCount,
+    #[msg("The same card index was given more than once")]
+    DuplicateCardIndices,
+
+    // Farm config errors
+    #[msg("Player cannot stake more than MAX_STAKED_CARDS_PER_PLAYER cards")]
+    TooManyStakedCards,
+    #[msg("Farm level slots cannot exceed MAX_STAKED_CARDS_PER_PLAYER")]
+    InvalidFarmLevel,
+    #[msg("Farm config has no room for more levels")]
+    FarmConfigFull,
+    #[msg("Too many distinct cards to rearrange within the compute budget; stake cards manually")]
+    StakingSearchTooLarge,
+
+    // Card level errors
+    #[msg("Card level curve must be increasing in XP with bounded bonuses")]
+    InvalidCardLevel,
+
+    // Migration errors
+    #[msg("Account is already in the current layout")]
+    AccountAlreadyMigrated,
+    #[msg("Account data does not match the expected layout")]
+    InvalidAccountLayout,
 }
@@ -17,6 +17,258 @@ pub fn reward_after_halvings(initial: u64, halvings: u64) -> u64 {
     initial.checked_shr(halvings as u32).unwrap_or(0)
 }
 
+/// Total emission between `from_slot` and `to_slot`, split at every halving
+/// boundary so each segment is credited at the rate in force during it.
+/// The multiplier is applied per segment (scaled by REWARD_RATE_MULTIPLIER_SCALE).
+/// A zero `halving_interval` disables halving and emits at a flat rate.
+pub fn calculate_halving_emission(
+    from_slot: u64,
+    to_slot: u64,
+    start_slot: u64,
+    halving_interval: u64,
+    initial_reward_rate: u64,
+    reward_rate_multiplier: u64,
+) -> u128 {
+    let max_halvings = calculate_max_halvings(initial_reward_rate);
+
+    let mut reward: u128 = 0;
+    let mut cursor = from_slot.max(start_slot);
+    while cursor < to_slot {
+        let halvings = if halving_interval > 0 {
+            calculate_halvings(cursor, start_slot, halving_interval)
+        } else {
+            0
+        };
+        // Rate is zero from here on
+        if halvings >= max_halvings {
+            break;
+        }
+        let next_boundary = if halving_interval > 0 {
+            start_slot
+                .saturating_add((halvings + 1).saturating_mul(halving_interval))
+                .min(to_slot)
+        } else {
+            to_slot
+        };
+
+        let rate = (reward_after_halvings(initial_reward_rate, halvings) as u128)
+            .saturating_mul(reward_rate_multiplier as u128)
+            / REWARD_RATE_MULTIPLIER_SCALE as u128;
+        reward = reward.saturating_add(rate.saturating_mul((next_boundary - cursor) as u128));
+
+        cursor = next_boundary;
+    }
+    reward
+}
+
+/// Spreads `reward` over `total_hashpower`, folding in the scaled remainder left
+/// by the previous update. Returns the accumulator increment and the new remainder
+/// (both scaled by ACC_SCALE), so no emitted reward is lost to rounding.
+pub fn distribute_reward(reward: u128, remainder: u64, total_hashpower: u64) -> (u128, u64) {
+    let scaled = reward
+        .saturating_mul(ACC_SCALE)
+        .saturating_add(remainder as u128);
+    let total_hashpower = total_hashpower as u128;
+    (scaled / total_hashpower, (scaled % total_hashpower) as u64)
+}
+
+/// Player's claimable tokens for an accumulator delta, plus the sub-token
+/// remainder (scaled by ACC_SCALE) to carry into their next claim
+pub fn calculate_pending_rewards(hashpower: u64, acc_delta: u128, remainder: u64) -> (u128, u64) {
+    let scaled = (hashpower as u128)
+        .saturating_mul(acc_delta)
+        .saturating_add(remainder as u128);
+    (scaled / ACC_SCALE, (scaled % ACC_SCALE) as u64)
+}
+
+/// Maps a random value onto `min..=max` (multiplier scaled by 1000)
+pub fn roll_reward_multiplier(random_value: u32, min: u32, max: u32) -> u64 {
+    let span = (max.saturating_sub(min) as u64) + 1;
+    min as u64 + (random_value as u64 % span)
+}
+
+/// Checks a referral code's length and that it only uses lowercase letters,
+/// digits and '_', so each code has exactly one spelling
+pub fn validate_referral_code(code: &str) -> Result<()> {
+    require!(
+        (MIN_REFERRAL_CODE_LEN..=MAX_REFERRAL_CODE_LEN).contains(&code.len())
+            && code
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_'),
+        PonzimonError::InvalidReferralCode
+    );
+    Ok(())
+}
+
+/// Splits a marketplace sale into (burn, protocol fee, seller proceeds). The
+/// protocol's `fee_bps` cut is divided between burn and fees by `burn_rate`.
+pub fn split_sale_price(price: u64, fee_bps: u16, burn_rate: u8) -> (u64, u64, u64) {
+    let cut = (price as u128 * fee_bps as u128 / 10_000) as u64;
+    let burn = (cut as u128 * burn_rate as u128 / 100) as u64;
+    (burn, cut - burn, price - cut)
+}
+
+/// A card that may take part in a staking rearrangement
+#[derive(Clone, Copy)]
+pub struct StakingCandidate {
+    pub hashpower: u64,
+    pub berry_consumption: u64,
+    pub staked: bool,
+}
+
+/// Cards with identical stats, currently staked ones first
+struct CandidateGroup {
+    hashpower: u64,
+    berry_consumption: u64,
+    members: Vec<usize>,
+}
+
+/// Returns the indices of the candidates whose staking maximises total hashpower
+/// while using at most `slots` cards and `berry_capacity` berries.
+///
+/// Identical cards are grouped and the bounded knapsack is solved by an iterative
+/// branch and bound over the groups, so memory stays proportional to the number of
+/// distinct card types. Among equal-stat cards, already staked ones are kept.
+/// Returns `None` if the search visits more than `max_nodes` nodes, which keeps
+/// the compute cost bounded for adversarial card sets.
+pub fn optimal_staking(
+    candidates: &[StakingCandidate],
+    slots: u64,
+    berry_capacity: u64,
+    max_nodes: u32,
+) -> Option<Vec<usize>> {
+    let mut groups: Vec<CandidateGroup> = Vec::new();
+    for (i, c) in candidates.iter().enumerate() {
+        if c.hashpower == 0 {
+            continue;
+        }
+        match groups
+            .iter_mut()
+            .find(|g| g.hashpower == c.hashpower && g.berry_consumption == c.berry_consumption)
+        {
+            Some(g) => g.members.push(i),
+            None => groups.push(CandidateGroup {
+                hashpower: c.hashpower,
+                berry_consumption: c.berry_consumption,
+                members: vec![i],
+            }),
+        }
+    }
+    for g in groups.iter_mut() {
+        g.members.sort_by_key(|&i| !candidates[i].staked);
+    }
+    groups.sort_by(|a, b| {
+        b.hashpower
+            .cmp(&a.hashpower)
+            .then(a.berry_consumption.cmp(&b.berry_consumption))
+    });
+
+    // Best hashpower-per-berry among groups[i..], as (hashpower, berries)
+    let mut best_ratio = vec![(0u64, 1u64); groups.len() + 1];
+    for i in (0..groups.len()).rev() {
+        let (hp, berries) = best_ratio[i + 1];
+        let g = &groups[i];
+        best_ratio[i] =
+            if g.hashpower as u128 * berries as u128 > hp as u128 * g.berry_consumption as u128 {
+                (g.hashpower, g.berry_consumption)
+            } else {
+                (hp, berries)
+            };
+    }
+    // Cards and their total hashpower in groups[i..], for the best fill of the slots
+    let mut cards_from = vec![0u64; groups.len() + 1];
+    let mut hashpower_from = vec![0u128; groups.len() + 1];
+    for i in (0..groups.len()).rev() {
+        let g = &groups[i];
+        cards_from[i] = cards_from[i + 1] + g.members.len() as u64;
+        hashpower_from[i] = hashpower_from[i + 1] + g.members.len() as u128 * g.hashpower as u128;
+    }
+    let upper_bound = |level: usize, slots_left: u64, berries_left: u64| -> u128 {
+        // Strongest `slots_left` cards, ignoring berries
+        let full = level
+            + cards_from[level..].partition_point(|&c| cards_from[level] - c <= slots_left)
+            - 1;
+        let mut by_slots = hashpower_from[level] - hashpower_from[full];
+        if full < groups.len() {
+            let rest = slots_left - (cards_from[level] - cards_from[full]);
+            by_slots += rest as u128 * groups[full].hashpower as u128;
+        }
+        let (hp, berries) = best_ratio[level];
+        if berries == 0 {
+            return by_slots;
+        }
+        by_slots.min(berries_left as u128 * hp as u128 / berries as u128)
+    };
+
+    let mut chosen: Vec<Option<u64>> = vec![None; groups.len()];
+    let mut best_counts = vec![0u64; groups.len()];
+    let mut best_value: u128 = 0;
+    let mut value: u128 = 0;
+    let mut slots_left = slots;
+    let mut berries_left = berry_capacity;
+    let mut level = 0usize;
+    let mut nodes = 0u32;
+    loop {
+        nodes += 1;
+        if nodes > max_nodes {
+            return None;
+        }
+        if level == groups.len() {
+            if value > best_value {
+                best_value = value;
+                best_counts = chosen.iter().map(|c| c.unwrap_or(0)).collect();
+            }
+            if level == 0 {
+                break;
+            }
+            level -= 1;
+            continue;
+        }
+
+        let g = &groups[level];
+        let next = match chosen[level] {
+            None if value + upper_bound(level, slots_left, berries_left) <= best_value => None,
+            None => {
+                let by_berries = berries_left
+                    .checked_div(g.berry_consumption)
+                    .unwrap_or(u64::MAX);
+                Some((g.members.len() as u64).min(slots_left).min(by_berries))
+            }
+            Some(count) => {
+                slots_left += count;
+                berries_left += count * g.berry_consumption;
+                value -= count as u128 * g.hashpower as u128;
+                count.checked_sub(1)
+            }
+        };
+        chosen[level] = next;
+        match next {
+            Some(count) => {
+                slots_left -= count;
+                berries_left -= count * g.berry_consumption;
+                value += count as u128 * g.hashpower as u128;
+                level += 1;
+            }
+            None if level == 0 => break,
+            None => level -= 1,
+        }
+    }
+
+    let mut selected: Vec<usize> = groups
+        .iter()
+        .zip(best_counts)
+        .flat_map(|(g, count)| g.members[..count as usize].iter().copied())
+        .collect();
+    selected.sort_unstable();
+    Some(selected)
+}
+
+/// Referrer's `referral_bps` share of a mining payout. It is paid on top of the
+/// player's rewards, so it is capped by the `remaining_supply` left to emit.
+pub fn mining_referral_share(pending: u64, referral_bps: u16, remaining_supply: u64) -> u64 {
+    ((pending as u128 * referral_bps as u128 / 10_000) as u64).min(remaining_supply)
+}
+
 // Security helper functions
 
 /// Validates that a card index is within bounds for a player's cards
@@ -78,3 +330,302 @@ pub fn get_next_rarity(current_rarity: u8) -> Option<u8> {
         _ => None,                    // Invalid rarity
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roll_reward_multiplier_stays_in_bounds() {
+        assert_eq!(roll_reward_multiplier(0, 500, 1500), 500);
+        assert_eq!(roll_reward_multiplier(1000, 500, 1500), 1500);
+        assert_eq!(roll_reward_multiplier(1001, 500, 1500), 500);
+        assert_eq!(roll_reward_multiplier(u32::MAX, 1000, 1000), 1000);
+    }
+
+    #[test]
+    fn test_validate_referral_code() {
+        assert!(validate_referral_code("ponzi_01").is_ok());
+        assert!(validate_referral_code("abc").is_ok());
+        assert!(validate_referral_code("abcdefghijklmnop").is_ok());
+
+        assert!(validate_referral_code("ab").is_err());
+        assert!(validate_referral_code("abcdefghijklmnopq").is_err());
+        assert!(validate_referral_code("Ponzi").is_err());
+        assert!(validate_referral_code("ponzi-01").is_err());
+        assert!(validate_referral_code("pönzi").is_err());
+    }
+
+    #[test]
+    fn test_split_sale_price() {
+        // 5% cut, 75% of which is burned
+        assert_eq!(
+            split_sale_price(1_000_000, 500, 75),
+            (37_500, 12_500, 950_000)
+        );
+        assert_eq!(split_sale_price(1_000_000, 0, 75), (0, 0, 1_000_000));
+        assert_eq!(split_sale_price(1_000_000, 500, 0), (0, 50_000, 950_000));
+
+        let (burn, fee, seller) = split_sale_price(u64::MAX, 5_000, 33);
+        assert_eq!(burn + fee + seller, u64::MAX);
+    }
+
+    #[test]
+    fn test_mining_referral_share() {
+        assert_eq!(mining_referral_share(1_000_000, 1_000, u64::MAX), 100_000);
+        assert_eq!(mining_referral_share(1_000_000, 0, u64::MAX), 0);
+        // Never emits past the supply cap
+        assert_eq!(mining_referral_share(1_000_000, 1_000, 40_000), 40_000);
+        assert_eq!(mining_referral_share(u64::MAX, 10_000, u64::MAX), u64::MAX);
+    }
+
+    fn candidates(stats: &[(u64, u64, bool)]) -> Vec<StakingCandidate> {
+        stats
+            .iter()
+            .map(|&(hashpower, berry_consumption, staked)| StakingCandidate {
+                hashpower,
+                berry_consumption,
+                staked,
+            })
+            .collect()
+    }
+
+    /// Exhaustive reference for small inventories
+    fn brute_force_staking(cards: &[StakingCandidate], slots: u64, capacity: u64) -> u64 {
+        (0u32..1 << cards.len())
+            .filter_map(|mask| {
+                let picked = cards
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0);
+                let (count, berries, hashpower) = picked.fold((0, 0, 0), |(n, b, h), (_, c)| {
+                    (n + 1, b + c.berry_consumption, h + c.hashpower)
+                });
+                (count <= slots && berries <= capacity).then_some(hashpower)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn test_optimal_staking_respects_both_limits() {
+        // One mega rare fills the berries, but two rares use the same slots better
+        let cards = candidates(&[
+            (2916, 128, false),
+            (36, 8, false),
+            (36, 8, false),
+            (4, 2, false),
+        ]);
+        assert_eq!(
+            optimal_staking(&cards, 2, 100, MAX_STAKING_SEARCH_NODES).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            optimal_staking(&cards, 2, 200, MAX_STAKING_SEARCH_NODES).unwrap(),
+            vec![0, 1]
+        );
+        assert_eq!(
+            optimal_staking(&cards, 4, 200, MAX_STAKING_SEARCH_NODES).unwrap(),
+            vec![0, 1, 2, 3]
+        );
+        assert!(optimal_staking(&cards, 0, 200, MAX_STAKING_SEARCH_NODES)
+            .unwrap()
+            .is_empty());
+        assert!(optimal_staking(&[], 5, 200, MAX_STAKING_SEARCH_NODES)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_optimal_staking_keeps_staked_duplicates() {
+        let cards = candidates(&[(12, 4, false), (12, 4, true), (12, 4, false)]);
+        assert_eq!(
+            optimal_staking(&cards, 1, 10, MAX_STAKING_SEARCH_NODES).unwrap(),
+            vec![1]
+        );
+    }
+
+    #[test]
+    fn test_optimal_staking_gives_up_past_node_budget() {
+        let cards = candidates(&[(36, 8, false), (12, 4, false), (4, 2, false)]);
+        assert_eq!(optimal_staking(&cards, 2, 10, 2), None);
+        assert_eq!(optimal_staking(&cards, 2, 10, 100), Some(vec![0, 2]));
+    }
+
+    #[test]
+    fn test_optimal_staking_matches_brute_force() {
+        let stats = [
+            (4, 2),
+            (12, 4),
+            (36, 8),
+            (108, 16),
+            (324, 32),
+            (5, 0),
+            (40, 13),
+        ];
+        let mut seed = 0x2545_f491u64;
+        for _ in 0..200 {
+            let mut next = || {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                seed >> 33
+            };
+            let len = (next() % 12) as usize;
+            let cards: Vec<StakingCandidate> = (0..len)
+                .map(|_| {
+                    let (hashpower, berry_consumption) =
+                        stats[(next() % stats.len() as u64) as usize];
+                    StakingCandidate {
+                        hashpower,
+                        berry_consumption,
+                        staked: next() % 2 == 0,
+                    }
+                })
+                .collect();
+            let slots = next() % 8;
+            let capacity = next() % 120;
+
+            let selected =
+                optimal_staking(&cards, slots, capacity, MAX_STAKING_SEARCH_NODES).unwrap();
+            let berries: u64 = selected.iter().map(|&i| cards[i].berry_consumption).sum();
+            let hashpower: u64 = selected.iter().map(|&i| cards[i].hashpower).sum();
+            assert!(selected.len() as u64 <= slots);
+            assert!(berries <= capacity);
+            assert_eq!(hashpower, brute_force_staking(&cards, slots, capacity));
+        }
+    }
+
+    #[test]
+    fn test_halving_emission_within_one_period() {
+        // 10 slots at 100/slot, 1x multiplier
+        let reward = calculate_halving_emission(0, 10, 0, 47_250, 100, 1000);
+        assert_eq!(reward, 1_000);
+    }
+
+    #[test]
+    fn test_halving_emission_spans_boundary() {
+        // Slots 90..100 at full rate, 100..110 at half rate
+        let reward = calculate_halving_emission(90, 110, 0, 100, 100, 1000);
+        assert_eq!(reward, 10 * 100 + 10 * 50);
+
+        // Same span claimed in two parts credits the same amount
+        let first = calculate_halving_emission(90, 100, 0, 100, 100, 1000);
+        let second = calculate_halving_emission(100, 110, 0, 100, 100, 1000);
+        assert_eq!(first + second, reward);
+    }
+
+    #[test]
+    fn test_halving_emission_applies_multiplier_and_start_slot() {
+        // Nothing is emitted before start_slot; multiplier 1.5x
+        let reward = calculate_halving_emission(0, 1_010, 1_000, 100, 100, 1500);
+        assert_eq!(reward, 10 * 150);
+    }
+
+    #[test]
+    fn test_halving_emission_zero_interval_is_flat() {
+        let reward = calculate_halving_emission(0, 1_000, 0, 0, 100, 1000);
+        assert_eq!(reward, 100_000);
+    }
+
+    #[test]
+    fn test_halving_emission_stops_after_max_halvings() {
+        // rate 4 halves to 2, 1, then 0 after three halvings
+        let reward = calculate_halving_emission(0, 1_000, 0, 10, 4, 1000);
+        assert_eq!(reward, 40 + 20 + 10);
+    }
+}
+
+#[cfg(test)]
+mod accumulator_tests {
+    use super::*;
+
+    struct SimPlayer {
+        hashpower: u64,
+        last_acc: u128,
+        remainder: u64,
+        claimed: u128,
+    }
+
+    impl SimPlayer {
+        fn new(hashpower: u64, acc: u128) -> Self {
+            Self {
+                hashpower,
+                last_acc: acc,
+                remainder: 0,
+                claimed: 0,
+            }
+        }
+
+        fn settle(&mut self, acc: u128) {
+            let (pending, remainder) =
+                calculate_pending_rewards(self.hashpower, acc - self.last_acc, self.remainder);
+            self.claimed += pending;
+            self.remainder = remainder;
+            self.last_acc = acc;
+        }
+    }
+
+    /// Asserts claims + every carried remainder account for all emitted reward
+    fn assert_conserved(players: &[SimPlayer], pool_remainder: u64, emitted: u128) {
+        let claimed: u128 = players.iter().map(|p| p.claimed).sum();
+        let player_remainders: u128 = players.iter().map(|p| p.remainder as u128).sum();
+        assert_eq!(
+            claimed * ACC_SCALE + player_remainders + pool_remainder as u128,
+            emitted * ACC_SCALE
+        );
+    }
+
+    #[test]
+    fn test_distribute_reward_carries_remainder() {
+        // 10 tokens over 3 hashpower leaves 1 scaled unit undistributed
+        let (increment, remainder) = distribute_reward(10, 0, 3);
+        assert_eq!(increment, 10 * ACC_SCALE / 3);
+        assert_eq!(remainder, 1);
+
+        // The carried unit is folded into the next update
+        let (increment, remainder) = distribute_reward(10, 2, 3);
+        assert_eq!(increment, (10 * ACC_SCALE + 2) / 3);
+        assert_eq!(remainder, 0);
+    }
+
+    #[test]
+    fn test_many_updates_conserve_emission() {
+        // Hashpower totals larger than ACC_SCALE make the old flooring lose
+        // whole tokens on every update
+        let mut players = vec![
+            SimPlayer::new(1_000_000_000_007, 0),
+            SimPlayer::new(333_333_333_331, 0),
+            SimPlayer::new(7, 0),
+        ];
+        let mut acc: u128 = 0;
+        let mut pool_remainder: u64 = 0;
+        let mut emitted: u128 = 0;
+        let mut seed: u64 = 0x5eed;
+
+        for step in 0..2_000u64 {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            let reward = (seed >> 40) as u128;
+            let total_hashpower: u64 = players.iter().map(|p| p.hashpower).sum();
+
+            let (increment, remainder) = distribute_reward(reward, pool_remainder, total_hashpower);
+            acc += increment;
+            pool_remainder = remainder;
+            emitted += reward;
+
+            // Players claim at different cadences; hashpower only changes after a settle
+            let idx = (seed % 3) as usize;
+            players[idx].settle(acc);
+            if step % 97 == 0 {
+                players[idx].hashpower += seed % 1_000 + 1;
+            }
+        }
+
+        for player in players.iter_mut() {
+            player.settle(acc);
+        }
+        assert_conserved(&players, pool_remainder, emitted);
+    }
+}
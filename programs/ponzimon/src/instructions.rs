@@ -17,18 +17,61 @@ pub struct FarmUpgraded {
 pub struct CardStaked {
     pub player: Pubkey,
     pub card_index: u8,
+    pub card_serial: u32,
 }
 
 #[event]
 pub struct CardUnstaked {
     pub player: Pubkey,
     pub card_index: u8,
+    pub card_serial: u32,
+}
+
+#[event]
+pub struct CardsStaked {
+    pub player: Pubkey,
+    pub card_indices: Vec<u8>,
+    pub card_serials: Vec<u32>,
+}
+
+#[event]
+pub struct CardsUnstaked {
+    pub player: Pubkey,
+    pub card_indices: Vec<u8>,
+    pub card_serials: Vec<u32>,
+}
+
+#[event]
+pub struct CardLevelledUp {
+    pub player: Pubkey,
+    pub card_serial: u32,
+    pub level: u8,
+    pub hashpower: u16,
+    pub berry_consumption: u8,
+}
+
+#[event]
+pub struct CardLevelCurveUpdated {
+    pub level: u8,
+    pub xp_required: u32,
+    pub hashpower_bonus_bps: u16,
+    pub berry_increase: u8,
+}
+
+#[event]
+pub struct StakingRearranged {
+    pub player: Pubkey,
+    pub staked_serials: Vec<u32>,
+    pub unstaked_serials: Vec<u32>,
+    pub total_hashpower: u64,
+    pub berries: u64,
 }
 
 #[event]
 pub struct CardDiscarded {
     pub player: Pubkey,
     pub card_index: u8,
+    pub card_serial: u32,
 }
 
 #[event]
@@ -37,6 +80,7 @@ pub struct BoosterOpened {
     // Events have a size limit, so we can't log the full card details.
     // We'll log the card types as a simple array.
     pub card_types: [u8; 5],
+    pub card_serials: [u32; 5], // 0 where no card was drawn
 }
 
 #[event]
@@ -44,12 +88,61 @@ pub struct CardsRecycled {
     pub player: Pubkey,
     pub successful_upgrades: u8, // Number of cards that were successfully upgraded
     pub total_recycled: u8,      // Total number of cards that were recycled
+    pub recycled_serials: Vec<u32>,
+    pub new_card_serials: Vec<u32>,
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
 /// INTERNAL: update the global accumulator
 /// ────────────────────────────────────────────────────────────────────────────
-fn update_pool(gs: &mut GlobalState, slot_now: u64) {
+fn update_pool(
+    gs: &mut GlobalState,
+    schedule: &EmissionSchedule,
+    history: &mut RateHistory,
+    slot_now: u64,
+) {
+    accrue_pool(gs, schedule, slot_now);
+    // Record any rate change seen since the last entry (halving, segment, dust shutdown)
+    record_rate_history(gs, schedule, history, slot_now, false);
+}
+
+/// Base reward rate (before the multiplier) in force at `slot`
+fn base_reward_rate_at(gs: &GlobalState, schedule: &EmissionSchedule, slot: u64) -> u64 {
+    if let Some(rate) = schedule.rate_at(slot) {
+        return rate;
+    }
+    let halvings = if gs.halving_interval > 0 {
+        calculate_halvings(slot, gs.start_slot, gs.halving_interval)
+    } else {
+        0
+    };
+    reward_after_halvings(gs.reward_rate, halvings)
+}
+
+/// Appends the current rate and accumulator to the history. Unless `force` is
+/// set, nothing is written when the rate and multiplier are unchanged.
+fn record_rate_history(
+    gs: &GlobalState,
+    schedule: &EmissionSchedule,
+    history: &mut RateHistory,
+    slot: u64,
+    force: bool,
+) {
+    let entry = RateHistoryEntry {
+        slot,
+        reward_rate: base_reward_rate_at(gs, schedule, slot),
+        reward_rate_multiplier: gs.reward_rate_multiplier,
+        acc_tokens_per_hashpower: gs.acc_tokens_per_hashpower,
+        total_hashpower: gs.total_hashpower,
+    };
+    if force {
+        history.push(entry);
+    } else {
+        history.record_if_changed(entry);
+    }
+}
+
+fn accrue_pool(gs: &mut GlobalState, schedule: &EmissionSchedule, slot_now: u64) {
     // Security: If the current slot is before the designated start slot,
     // no rewards should be processed.
     if slot_now < gs.start_slot {
@@ -57,11 +150,18 @@ fn update_pool(gs: &mut GlobalState, slot_now: u64) {
         return;
     }
 
-    if slot_now <= gs.last_reward_slot || gs.total_hashpower == 0 {
+    if slot_now <= gs.last_reward_slot
+        || (gs.total_hashpower == 0 && gs.idle_emission_policy == IDLE_EMISSION_DROP)
+    {
         gs.last_reward_slot = slot_now;
         return;
     }
-    let rate_now = gs.reward_rate;
+    let halvings = if gs.halving_interval > 0 {
+        calculate_halvings(gs.last_reward_slot, gs.start_slot, gs.halving_interval)
+    } else {
+        0
+    };
+    let rate_now = reward_after_halvings(gs.reward_rate, halvings);
 
     /* remaining supply after accounting for burns */
     let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
@@ -73,30 +173,82 @@ fn update_pool(gs: &mut GlobalState, slot_now: u64) {
         0 // Avoid division by zero, effectively disabling dust threshold if misconfigured
     };
     // Check if we're close to depleting the supply
-    if remaining_supply <= dust_threshold || rate_now == 0 {
+    // A zero legacy rate only ends mining when no emission schedule takes over
+    if remaining_supply <= dust_threshold || (rate_now == 0 && !schedule.is_active()) {
         // Then set rate to zero to prevent future mining
         gs.reward_rate = 0;
         gs.last_reward_slot = slot_now;
+        // Emission rolled over from idle periods was already counted, pay it out
+        if gs.total_hashpower > 0 && gs.rolled_over_emission > 0 {
+            credit_hashpower(gs, 0);
+        }
         return;
     }
 
-    // Apply the random multiplier to the base rate
-    let effective_rate_now = (rate_now as u128)
-        .saturating_mul(gs.reward_rate_multiplier as u128)
-        .saturating_div(REWARD_RATE_MULTIPLIER_SCALE as u128);
-
-    let slots_elapsed = (slot_now - gs.last_reward_slot) as u128;
-    let mut reward = slots_elapsed
-        .checked_mul(effective_rate_now as u128)
-        .unwrap_or(u128::MAX);
+    // Slots before the emission schedule starts use the legacy reward_rate,
+    // split at halving boundaries; the schedule's segments govern the rest.
+    // The random multiplier is applied to the rate in force during each segment.
+    let legacy_end_slot = schedule
+        .first_start_slot()
+        .map_or(slot_now, |first| first.min(slot_now));
+    let mut reward = calculate_halving_emission(
+        gs.last_reward_slot,
+        legacy_end_slot,
+        gs.start_slot,
+        gs.halving_interval,
+        gs.reward_rate,
+        gs.reward_rate_multiplier,
+    );
+    if schedule.is_active() {
+        reward = reward.saturating_add(schedule.emission_between(
+            gs.last_reward_slot,
+            slot_now,
+            gs.reward_rate_multiplier,
+        ));
+    }
     reward = reward.min(remaining_supply as u128); // clamp to cap
 
-    gs.acc_tokens_per_hashpower += reward * ACC_SCALE / gs.total_hashpower as u128;
+    if gs.total_hashpower == 0 {
+        // Nobody is mining: park the emission according to the idle policy
+        if gs.idle_emission_policy == IDLE_EMISSION_TREASURY {
+            gs.treasury_emission_owed = gs.treasury_emission_owed.saturating_add(reward as u64);
+        } else {
+            gs.rolled_over_emission = gs.rolled_over_emission.saturating_add(reward as u64);
+        }
+    } else {
+        credit_hashpower(gs, reward);
+    }
     gs.cumulative_rewards = gs.cumulative_rewards.saturating_add(reward as u64);
 
     gs.last_reward_slot = slot_now;
 }
 
+/// Restarts the halving schedule at `slot` from the legacy rate in force then,
+/// so a new halving interval does not reprice the periods since `start_slot`.
+/// Emissions must already be settled up to `slot`.
+fn rebase_halving_schedule(gs: &mut GlobalState, slot: u64) {
+    if slot <= gs.start_slot {
+        return;
+    }
+    if gs.halving_interval > 0 {
+        let halvings = calculate_halvings(slot, gs.start_slot, gs.halving_interval);
+        gs.reward_rate = reward_after_halvings(gs.reward_rate, halvings);
+    }
+    gs.start_slot = slot;
+}
+
+/// Spreads `reward`, plus any emission rolled over from idle periods, across
+/// current hashpower. Caller guarantees `total_hashpower > 0`.
+fn credit_hashpower(gs: &mut GlobalState, reward: u128) {
+    let reward = reward.saturating_add(gs.rolled_over_emission as u128);
+    gs.rolled_over_emission = 0;
+
+    let (acc_increment, remainder) =
+        distribute_reward(reward, gs.acc_reward_remainder, gs.total_hashpower);
+    gs.acc_tokens_per_hashpower += acc_increment;
+    gs.acc_reward_remainder = remainder;
+}
+
 fn update_staking_pool(gs: &mut GlobalState, slot_now: u64) {
     // Security: If the current slot is before the designated start slot,
     // no rewards should be processed.
@@ -129,16 +281,136 @@ fn update_staking_pool(gs: &mut GlobalState, slot_now: u64) {
                 .saturating_mul(ACC_SCALE)
                 .saturating_div(gs.total_staked_tokens as u128),
         );
+        gs.cumulative_staking_rewards = gs
+            .cumulative_staking_rewards
+            .saturating_add(token_reward.min(u64::MAX as u128) as u64);
     }
 
     gs.last_staking_reward_slot = slot_now;
 }
 
+/// Mining rewards a player can claim against the current accumulator, and the
+/// sub-token remainder to carry into their next claim. Shared with `preview_player`.
+fn pending_mining_rewards(player: &Player, gs: &GlobalState) -> (u64, u64) {
+    let (pending_u128, remainder) = calculate_pending_rewards(
+        player.total_hashpower,
+        gs.acc_tokens_per_hashpower
+            .saturating_sub(player.last_acc_tokens_per_hashpower),
+        player.acc_reward_remainder,
+    );
+    let mut pending = pending_u128 as u64;
+
+    // Clamp pending to remaining supply
+    let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
+    let remaining_supply = gs.total_supply.saturating_sub(minted_minus_burn);
+    if pending > remaining_supply {
+        pending = remaining_supply;
+    }
+    (pending, remainder)
+}
+
+/// Referrer's share of a mining payout, emitted on top of it from the supply
+/// that has not been emitted yet
+fn pending_referral_share(player: &Player, gs: &GlobalState, pending: u64) -> u64 {
+    if player.referrer.is_none() {
+        return 0;
+    }
+    let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
+    let remaining_supply = gs.total_supply.saturating_sub(minted_minus_burn);
+    mining_referral_share(pending, gs.mining_referral_bps, remaining_supply)
+}
+
+/// Staking rewards accrued since the player's last checkpoint: (SOL, tokens).
+/// Token rewards exclude `claimed_token_rewards` already banked on the player.
+fn pending_staking_rewards(player: &Player, gs: &GlobalState) -> (u64, u64) {
+    let pending_sol = ((player.staked_tokens as u128)
+        .checked_mul(
+            gs.acc_sol_rewards_per_token
+                .saturating_sub(player.last_acc_sol_rewards_per_token),
+        )
+        .unwrap_or(0)
+        / ACC_SCALE) as u64;
+
+    let pending_tokens = ((player.staked_tokens as u128)
+        .checked_mul(
+            gs.acc_token_rewards_per_token
+                .saturating_sub(player.last_acc_token_rewards_per_token),
+        )
+        .unwrap_or(0)
+        / ACC_SCALE) as u64;
+
+    (pending_sol, pending_tokens)
+}
+
+/// Resolves the referral chain above `direct_referrer`, up to `depth` levels in
+/// total. For every level past the first, `remaining_accounts` must hold the
+/// previous referrer's Player account followed by the account that receives the
+/// payout. The walk stops when a referrer never played or has no referrer of
+/// their own. Returns `(upline wallet, recipient)` for levels 2 and up.
+fn resolve_referral_chain<'c, 'info>(
+    buyer: Pubkey,
+    direct_referrer: Pubkey,
+    depth: usize,
+    token_mint: Pubkey,
+    program_id: &Pubkey,
+    remaining_accounts: &'c [AccountInfo<'info>],
+) -> Result<Vec<(Pubkey, &'c AccountInfo<'info>)>> {
+    let mut visited = vec![buyer, direct_referrer];
+    let mut current = direct_referrer;
+    let mut accounts = remaining_accounts.iter();
+    let mut uplines = Vec::new();
+
+    for _ in 1..depth {
+        let player_info = accounts
+            .next()
+            .ok_or(PonzimonError::InvalidRemainingAccounts)?;
+        let (expected_player, _) = Pubkey::find_program_address(
+            &[PLAYER_SEED, current.as_ref(), token_mint.as_ref()],
+            program_id,
+        );
+        require_keys_eq!(
+            player_info.key(),
+            expected_player,
+            PonzimonError::InvalidPlayerAccount
+        );
+        // Referrer wallets do not need a farm of their own
+        if player_info.data_is_empty() {
+            break;
+        }
+        require_keys_eq!(
+            *player_info.owner,
+            *program_id,
+            PonzimonError::InvalidPlayerAccount
+        );
+        let upline_player = Player::try_deserialize(&mut &player_info.data.borrow()[..])?;
+        let Some(next) = upline_player.referrer else {
+            break;
+        };
+        require!(
+            !visited.contains(&next),
+            PonzimonError::ReferralCycleDetected
+        );
+
+        let recipient = accounts
+            .next()
+            .ok_or(PonzimonError::InvalidRemainingAccounts)?;
+        uplines.push((next, recipient));
+        visited.push(next);
+        current = next;
+    }
+
+    Ok(uplines)
+}
+
 /// Helper to settle and mint rewards for a player.
 /// Returns Ok(amount_claimed) or Ok(0) if nothing to claim.
 fn settle_and_mint_rewards<'info>(
     player: &mut Box<Account<'info, Player>>,
     gs: &mut Account<'info, GlobalState>,
+    emission_schedule: &EmissionSchedule,
+    rate_history: &mut RateHistory,
+    vesting_account: Option<&mut Account<'info, VestingAccount>>,
+    referrer_token_account: Option<&Account<'info, TokenAccount>>,
     now: u64,
     player_token_account: &AccountInfo<'info>,
     token_mint: &AccountInfo<'info>,
@@ -147,29 +419,39 @@ fn settle_and_mint_rewards<'info>(
     global_state_bump: u8,
 ) -> Result<u64> {
     // update pool to now
-    update_pool(gs, now);
+    update_pool(gs, emission_schedule, rate_history, now);
 
     require!(
         now > player.last_claim_slot,
         PonzimonError::CooldownNotExpired
     );
 
-    // calculate pending
-    let pending_u128 = (player.total_hashpower as u128)
-        .checked_mul(
-            gs.acc_tokens_per_hashpower
-                .saturating_sub(player.last_acc_tokens_per_hashpower),
-        )
-        .unwrap_or(u128::MAX)
-        / ACC_SCALE;
-    let mut pending = pending_u128 as u64;
-
-    // Clamp pending to remaining supply
-    let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
-    let remaining_supply = gs.total_supply.saturating_sub(minted_minus_burn);
-    if pending > remaining_supply {
-        pending = remaining_supply;
+    // calculate pending, carrying the sub-token remainder to the next claim
+    let (pending, remainder) = pending_mining_rewards(player, gs);
+    player.acc_reward_remainder = remainder;
+
+    // Staked cards earn XP for the settled slots. Level-ups only count from now
+    // on, since this period's rewards were computed with the old hashpower.
+    let (old_hashpower, old_berries) = (player.total_hashpower, player.berries);
+    let (elapsed, curve) = (now - player.last_claim_slot, gs.card_level_curve);
+    for index in player.accrue_card_xp(elapsed, &curve) {
+        let card = player.cards[index as usize];
+        emit!(CardLevelledUp {
+            player: player.key(),
+            card_serial: card.serial,
+            level: card.level,
+            hashpower: card.hashpower,
+            berry_consumption: card.berry_consumption,
+        });
     }
+    gs.total_hashpower = safe_add_hashpower(
+        safe_sub_hashpower(gs.total_hashpower, old_hashpower)?,
+        player.total_hashpower,
+    )?;
+    gs.total_berries = safe_add_berries(
+        safe_sub_berries(gs.total_berries, old_berries)?,
+        player.berries,
+    )?;
 
     if pending == 0 {
         player.last_claim_slot = now;
@@ -181,11 +463,37 @@ fn settle_and_mint_rewards<'info>(
     player.last_claim_slot = now;
     player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
 
-    // Give the player their full rewards - no deduction for referrals
-    let player_amount = pending;
+    // Referral share is paid on top of the player's rewards, out of the
+    // protocol's unemitted supply, so it counts against the emission cap.
+    // Without a valid referrer token account it is held on the player and
+    // paid by the next settlement that has one.
+    let referral_share = pending_referral_share(player, gs, pending);
+    gs.cumulative_rewards = gs.cumulative_rewards.saturating_add(referral_share);
+    player.referral_rewards_owed = player.referral_rewards_owed.saturating_add(referral_share);
+    let referrer_token_account = referrer_token_account
+        .filter(|account| Some(account.owner) == player.referrer && account.mint == gs.token_mint);
+    let referral_amount = match referrer_token_account {
+        Some(_) => std::mem::take(&mut player.referral_rewards_owed),
+        None => 0,
+    };
+    player.total_earnings_for_referrer = player
+        .total_earnings_for_referrer
+        .saturating_add(referral_amount);
+
+    // Lock the vesting share; it stays in the rewards vault until withdrawn
+    let vested_amount = (pending as u128 * gs.vesting_percent as u128 / 100) as u64;
+    if vested_amount > 0 {
+        let vesting = vesting_account.ok_or(PonzimonError::VestingAccountRequired)?;
+        vesting.deposit(vested_amount, now, gs.vesting_duration_slots);
+    }
+    let player_amount = pending - vested_amount;
 
     // Update player total rewards (Effect)
-    player.total_rewards = player.total_rewards.saturating_add(player_amount);
+    player.total_rewards = player.total_rewards.saturating_add(pending);
+    gs.total_rewards_claimed = gs
+        .total_rewards_claimed
+        .saturating_add(player_amount)
+        .saturating_add(referral_amount);
 
     // signer seeds
     let token_mint_key = &token_mint.key();
@@ -196,19 +504,38 @@ fn settle_and_mint_rewards<'info>(
     ];
     let signer = &[&seeds[..]];
 
-    // mint to player - they get their full rewards
-    token::transfer(
-        CpiContext::new_with_signer(
-            token_program.clone(),
-            Transfer {
-                from: rewards_vault.clone(),
-                to: player_token_account.clone(),
-                authority: gs.to_account_info(),
-            },
-            signer,
-        ),
-        player_amount,
-    )?;
+    // transfer to player - everything that is not vesting
+    if player_amount > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                Transfer {
+                    from: rewards_vault.clone(),
+                    to: player_token_account.clone(),
+                    authority: gs.to_account_info(),
+                },
+                signer,
+            ),
+            player_amount,
+        )?;
+    }
+
+    if referral_amount > 0 {
+        if let Some(referrer_token_account) = referrer_token_account {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.clone(),
+                    Transfer {
+                        from: rewards_vault.clone(),
+                        to: referrer_token_account.to_account_info(),
+                        authority: gs.to_account_info(),
+                    },
+                    signer,
+                ),
+                referral_amount,
+            )?;
+        }
+    }
 
     Ok(pending)
 }
@@ -224,19 +551,7 @@ pub struct InitializeProgram<'info> {
     #[account(
         init,
         payer = authority,
-        space = 8  /* discriminator */
-        + 32 + 32 + 32          /* authority + mint + fees_wallet */
-        + 8  + 8                /* total_supply + burned_tokens */
-        + 8  + 8                /* cumulative_rewards + start_slot */
-        + 8  + 16 + 8           /* reward_rate + acc_tokens_per_hashpower + last_reward_slot */
-        + 1  + 1 + 1 + 8 + 8    /* burn_rate + referral_fee + prod + cooldown + dust_divisor */
-        + 8 + 8 + 8             /* initial_farm_purchase_fee_lamports + booster_pack_cost_microtokens + gamble_fee_lamports */
-        + 8 + 8                 /* total_berries + total_hashpower */
-        + 8 + 8                 /* total_global_gambles + total_global_gamble_wins */
-        + 8 + 8 + 8             /* total_booster_packs_opened + total_card_recycling_attempts + total_successful_card_recycling */
-        + 32 + 8 + 8 + 16 + 16 + 8 + 8 + 8 /* staking: sol_rewards_wallet + total_staked_tokens + staking_lockup_slots + acc_sol_rewards_per_token + acc_token_rewards_per_token + last_staking_reward_slot + token_reward_rate + total_sol_deposited */
-        + 8 + 8                 /* dynamic rewards: reward_rate_multiplier + last_rate_update_slot */
-        + 64, /* padding for future expansion */
+        space = GlobalState::SIZE,
         seeds=[GLOBAL_STATE_SEED, token_mint.key().as_ref()],
         bump
     )]
@@ -285,6 +600,7 @@ pub fn initialize_program(
     start_slot: u64,
     total_supply: u64,
     reward_rate: u64,
+    halving_interval: u64,
     cooldown_slots: Option<u64>,
     initial_farm_purchase_fee_lamports: Option<u64>,
     booster_pack_cost_microtokens: Option<u64>,
@@ -305,6 +621,7 @@ pub fn initialize_program(
 
     gs.start_slot = start_slot;
     gs.reward_rate = reward_rate;
+    gs.halving_interval = halving_interval;
 
     gs.acc_tokens_per_hashpower = 0;
     gs.last_reward_slot = start_slot;
@@ -342,6 +659,33 @@ pub fn initialize_program(
     // Dynamic rewards
     gs.reward_rate_multiplier = REWARD_RATE_MULTIPLIER_SCALE;
     gs.last_rate_update_slot = start_slot;
+    gs.reward_multiplier_min = DEFAULT_REWARD_MULTIPLIER_MIN;
+    gs.reward_multiplier_max = DEFAULT_REWARD_MULTIPLIER_MAX;
+    gs.rate_update_cooldown_slots = REWARD_RATE_UPDATE_COOLDOWN_SLOTS;
+
+    // Supply accounting
+    gs.total_rewards_claimed = 0;
+    gs.cumulative_staking_rewards = 0;
+    gs.total_staking_rewards_claimed = 0;
+    gs.acc_reward_remainder = 0;
+
+    // Idle emission is dropped until the admin picks a policy
+    gs.idle_emission_policy = IDLE_EMISSION_DROP;
+    gs.emission_treasury = Pubkey::default();
+    gs.rolled_over_emission = 0;
+    gs.treasury_emission_owed = 0;
+
+    // Vesting is off until the admin sets a percentage
+    gs.vesting_percent = 0;
+    gs.vesting_duration_slots = DEFAULT_VESTING_DURATION_SLOTS;
+
+    // Referrers share in mining claims only once the admin enables it
+    gs.mining_referral_bps = 0;
+
+    // Card transfers are free until the admin sets a fee
+    gs.card_transfer_fee = 0;
+    gs.marketplace_fee_bps = DEFAULT_MARKETPLACE_FEE_BPS;
+    gs.card_level_curve = CardLevel::default_curve();
 
     // Mint initial supply to rewards vault
     let preminted_supply = ctx.accounts.token_mint.supply;
@@ -418,34 +762,7 @@ pub struct PurchaseInitialFarm<'info> {
     #[account(
         init,
         payer = player_wallet,
-        space = 8      // discriminator
-            + 32       // owner: Pubkey
-            + 10       // farm: Farm (1+1+8)
-            + (MAX_CARDS_PER_PLAYER as usize * 6) // cards: [Card; MAX_CARDS_PER_PLAYER] - Card = 6 bytes (2+1+2+1)
-            + 1        // card_count: u8
-            + 16       // staked_cards_bitset: u128 (Changed from 8 to 16)
-            + 8        // berries: u64
-            + 8        // total_hashpower: u64
-            + 33       // referrer: Option<Pubkey> (1+32)
-            + 16       // last_acc_tokens_per_hashpower: u128
-            + 8        // last_claim_slot: u64
-            + 8        // last_upgrade_slot: u64
-            + 8        // total_rewards: u64
-            + 8        // total_gambles: u64
-            + 8        // total_gamble_wins: u64
-            // --- Consolidated randomness fields ---
-            + 130      // pending_action: PendingRandomAction enum (1 byte disc + 129 for largest Recycle variant)
-            + 32       // randomness_account: Pubkey
-            + 8        // commit_slot: u64
-            // --- Additional player stats ---
-            + 8        // total_earnings_for_referrer: u64
-            + 8        // total_booster_packs_opened: u64
-            + 8        // total_cards_recycled: u64
-            + 8        // successful_card_recycling: u64
-            + 8        // total_sol_spent: u64
-            + 8        // total_tokens_spent: u64
-            + 8 + 8 + 16 + 16 + 8  // Staking stats: staked_tokens + last_stake_slot + last_acc_sol_rewards_per_token + last_acc_token_rewards_per_token + claimed_token_rewards
-            + 64,      // padding: [u8; 64] for future expansion
+        space = Player::SIZE,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -456,6 +773,17 @@ pub struct PurchaseInitialFarm<'info> {
         bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
 
     /// CHECK: This is the fees recipient wallet from global_state
     #[account(
@@ -466,6 +794,43 @@ pub struct PurchaseInitialFarm<'info> {
     /// CHECK: This is the referrer's wallet. Optional. If provided, the wallet key is used as the referrer.
     #[account(mut)]
     pub referrer_wallet: Option<AccountInfo<'info>>,
+    /// Optional referral code. When given, `referrer_wallet` must be the code's owner.
+    #[account(
+        constraint = referral_code.token_mint == token_mint.key() @ PonzimonError::InvalidTokenMint
+    )]
+    pub referral_code: Option<Box<Account<'info, ReferralCode>>>,
+    /// Referrer stats, created on the first referral. Required with `referrer_wallet`.
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8  /* discriminator */
+        + 32 + 32  /* wallet + token_mint */
+        + 8        /* referee_count */
+        + 8 + 8    /* total_sol_earned + booster_tokens_earned */
+        + 8,       /* last_referral_slot */
+        seeds = [
+            REFERRER_SEED,
+            referrer_wallet.as_ref().map(|w| w.key()).unwrap_or_default().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub referrer_account: Option<Box<Account<'info, Referrer>>>,
+    #[account(
+        seeds = [REFERRAL_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub referral_config: Box<Account<'info, ReferralConfig>>,
+    #[account(
+        seeds = [CARD_CATALOG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub card_catalog: Box<Account<'info, CardCatalog>>,
+    #[account(
+        seeds = [FARM_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub farm_config: Box<Account<'info, FarmConfig>>,
     #[account(
         mut,
         constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
@@ -494,10 +859,13 @@ pub struct InitialFarmPurchased {
     pub farm_type: u8,
     pub initial_cards: u8,
     pub initial_hashpower: u64,
+    pub card_serials: Vec<u32>,
     pub slot: u64,
 }
 
-pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
+pub fn purchase_initial_farm<'info>(
+    ctx: Context<'_, '_, '_, 'info, PurchaseInitialFarm<'info>>,
+) -> Result<()> {
     let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
@@ -519,6 +887,15 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
     // The referrer is now derived from the optional `referrer_wallet` account.
     let referrer: Option<Pubkey> = ctx.accounts.referrer_wallet.as_ref().map(|acc| acc.key());
 
+    // A referral code pins the referrer to the code's owner; the wallet is
+    // still needed to receive the commission.
+    if let Some(referral_code) = &ctx.accounts.referral_code {
+        require!(
+            referrer == Some(referral_code.owner),
+            PonzimonError::ReferralCodeMismatch
+        );
+    }
+
     // Prevent self-referral.
     if let Some(ref r) = referrer {
         require!(
@@ -528,17 +905,35 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
     }
 
     // Make sure the reward pool is up to date before any state changes.
-    update_pool(gs, slot);
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        slot,
+    );
 
     // --- Fee and Referral Logic ---
     if let Some(referrer_wallet) = &ctx.accounts.referrer_wallet {
-        // A referrer is provided, so we split the fee into two transfers.
-        let referral_fee_lamports = gs
-            .initial_farm_purchase_fee_lamports
-            .saturating_mul(gs.referral_fee as u64)
-            .saturating_div(100);
-
-        let fees_wallet_amount = gs
+        // A referrer is provided, so the fee is split between the referral chain
+        // and the protocol.
+        let referral_config = &ctx.accounts.referral_config;
+        let referral_fee_lamports =
+            referral_config.level_share(gs.initial_farm_purchase_fee_lamports, 0, gs.referral_fee);
+
+        let referrer_account = ctx
+            .accounts
+            .referrer_account
+            .as_mut()
+            .ok_or(PonzimonError::ReferrerAccountMissing)?;
+        referrer_account.wallet = referrer_wallet.key();
+        referrer_account.token_mint = ctx.accounts.token_mint.key();
+        referrer_account.referee_count = referrer_account.referee_count.saturating_add(1);
+        referrer_account.total_sol_earned = referrer_account
+            .total_sol_earned
+            .saturating_add(referral_fee_lamports);
+        referrer_account.last_referral_slot = slot;
+
+        let mut fees_wallet_amount = gs
             .initial_farm_purchase_fee_lamports
             .saturating_sub(referral_fee_lamports);
 
@@ -559,7 +954,43 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
                 .saturating_add(referral_fee_lamports);
         }
 
-        // 2. Transfer the remaining protocol fee to the main fees wallet.
+        // 2. Pay the referrers further up the chain, each at their level's share.
+        let uplines = resolve_referral_chain(
+            ctx.accounts.player_wallet.key(),
+            referrer_wallet.key(),
+            referral_config.depth(),
+            ctx.accounts.token_mint.key(),
+            ctx.program_id,
+            ctx.remaining_accounts,
+        )?;
+        for (level, (upline, recipient)) in uplines.into_iter().enumerate() {
+            require_keys_eq!(
+                recipient.key(),
+                upline,
+                PonzimonError::ReferrerAccountMissing
+            );
+            let share = referral_config.level_share(
+                gs.initial_farm_purchase_fee_lamports,
+                level + 1,
+                gs.referral_fee,
+            );
+            if share == 0 {
+                continue;
+            }
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player_wallet.to_account_info(),
+                        to: recipient.clone(),
+                    },
+                ),
+                share,
+            )?;
+            fees_wallet_amount = fees_wallet_amount.saturating_sub(share);
+        }
+
+        // 3. Transfer the remaining protocol fee to the main fees wallet.
         if fees_wallet_amount > 0 {
             anchor_lang::system_program::transfer(
                 CpiContext::new(
@@ -588,12 +1019,7 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
 
     // player bootstrap
     player.owner = ctx.accounts.player_wallet.key();
-    let (total_cards, berry_capacity, _) = FARM_CONFIGS[1];
-    player.farm = Farm {
-        farm_type: 1,
-        total_cards,
-        berry_capacity,
-    };
+    player.farm = ctx.accounts.farm_config.farm(1)?;
 
     // Initialize arrays
     player.cards = [Card::default(); MAX_CARDS_PER_PLAYER as usize];
@@ -601,15 +1027,15 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
     player.staked_cards_bitset = 0; // No cards staked initially
 
     // Give player 3 starter cards using the IDs from data.ts (not staked initially)
+    let mut card_serials = Vec::with_capacity(STARTER_CARD_IDS.len());
     for &card_id in STARTER_CARD_IDS.iter() {
-        if let Some((rarity, hashpower, berry_consumption)) = get_card_by_id(card_id) {
-            let card = Card {
-                id: card_id,
-                rarity,
-                hashpower,
-                berry_consumption,
-            };
-            player.add_card(card)?;
+        if let Some(card) = ctx
+            .accounts
+            .card_catalog
+            .get(card_id)
+            .filter(|card| card.enabled)
+        {
+            card_serials.push(player.add_card(card.to_card())?);
         }
     }
 
@@ -649,7 +1075,13 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
     player.claimed_token_rewards = 0;
 
     // Initialize padding field
-    player.padding = [0u8; 64];
+    player.acc_reward_remainder = 0;
+    player.claim_destination = None;
+    player.claim_keeper = None;
+    player.next_card_serial = 0;
+    player.referral_rewards_owed = 0;
+    player.accepts_card_transfers = false;
+    player.padding = [0u8; 43];
 
     // global stats (Effect) - no initial berry consumption since cards aren't staked
     // gs.total_berries += 0; // No change needed
@@ -661,6 +1093,7 @@ pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
         farm_type: player.farm.farm_type,
         initial_cards: player.card_count,
         initial_hashpower: player.berries, // 0 since no cards are staked initially
+        card_serials,
         slot,
     });
 
@@ -688,6 +1121,30 @@ pub struct DiscardCard<'info> {
         bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    /// Required while vesting is enabled
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    /// Receives the mining referral share; without it the share accrues on the player
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
     #[account(
         mut,
         seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
@@ -736,6 +1193,10 @@ pub fn discard_card(ctx: Context<DiscardCard>, card_index: u8) -> Result<()> {
     settle_and_mint_rewards(
         player,
         gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
         &ctx.accounts.token_mint.to_account_info(),
@@ -745,6 +1206,7 @@ pub fn discard_card(ctx: Context<DiscardCard>, card_index: u8) -> Result<()> {
     )?;
 
     // Remove the card using the helper function
+    let card_serial = player.cards[card_index as usize].serial;
     player.remove_card(card_index)?;
 
     player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
@@ -752,16 +1214,23 @@ pub fn discard_card(ctx: Context<DiscardCard>, card_index: u8) -> Result<()> {
     emit!(CardDiscarded {
         player: player.key(),
         card_index,
+        card_serial,
     });
 
     Ok(())
 }
 
+/// Same as `discard_card`, addressing the card by serial so the call stays
+/// correct if other cards are removed first.
+pub fn discard_card_by_serial(ctx: Context<DiscardCard>, card_serial: u32) -> Result<()> {
+    let card_index = ctx.accounts.player.find_card_by_serial(card_serial)?;
+    discard_card(ctx, card_index)
+}
+
 /// ────────────────────────────────────────────────────────────────────────────
 ///  STAKE CARD
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-#[instruction(card_index: u8)]
 pub struct StakeCard<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
@@ -778,6 +1247,35 @@ pub struct StakeCard<'info> {
         bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        seeds = [FARM_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub farm_config: Box<Account<'info, FarmConfig>>,
+    /// Required while vesting is enabled
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    /// Receives the mining referral share; without it the share accrues on the player
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
     #[account(
         mut,
         seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
@@ -806,6 +1304,10 @@ pub fn stake_card(ctx: Context<StakeCard>, card_index: u8) -> Result<()> {
     settle_and_mint_rewards(
         player,
         gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
         &ctx.accounts.token_mint.to_account_info(),
@@ -814,6 +1316,9 @@ pub fn stake_card(ctx: Context<StakeCard>, card_index: u8) -> Result<()> {
         ctx.bumps.global_state,
     )?;
 
+    // Pick up any rebalance of the player's farm level
+    player.sync_farm(&ctx.accounts.farm_config)?;
+
     // Security: Validate card index bounds
     validate_card_index(card_index, player.card_count as usize)?;
 
@@ -858,52 +1363,21 @@ pub fn stake_card(ctx: Context<StakeCard>, card_index: u8) -> Result<()> {
     emit!(CardStaked {
         player: player.key(),
         card_index,
+        card_serial: player.cards[card_index as usize].serial,
     });
 
     Ok(())
 }
 
-/// ────────────────────────────────────────────────────────────────────────────
-///  UNSTAKE CARD
-/// ────────────────────────────────────────────────────────────────────────────
-#[derive(Accounts)]
-#[instruction(card_index: u8)]
-pub struct UnstakeCard<'info> {
-    #[account(mut)]
-    pub player_wallet: Signer<'info>,
-    #[account(
-        mut,
-        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
-        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
-        bump
-    )]
-    pub player: Box<Account<'info, Player>>,
-    #[account(
-        mut,
-        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
-        bump,
-    )]
-    pub global_state: Account<'info, GlobalState>,
-    #[account(
-        mut,
-        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
-        bump,
-    )]
-    pub rewards_vault: Account<'info, TokenAccount>,
-    #[account(
-        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
-    )]
-    pub token_mint: Account<'info, Mint>,
-    #[account(
-        mut,
-        constraint = player_token_account.mint == global_state.token_mint,
-        constraint = player_token_account.owner == player_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
-    )]
-    pub player_token_account: Box<Account<'info, TokenAccount>>,
-    pub token_program: Program<'info, Token>,
+/// Same as `stake_card`, addressing the card by serial.
+pub fn stake_card_by_serial(ctx: Context<StakeCard>, card_serial: u32) -> Result<()> {
+    let card_index = ctx.accounts.player.find_card_by_serial(card_serial)?;
+    stake_card(ctx, card_index)
 }
 
-pub fn unstake_card(ctx: Context<UnstakeCard>, card_index: u8) -> Result<()> {
+/// Stakes several cards with a single reward settlement. All-or-nothing: every
+/// index must pass the same checks as `stake_card`.
+pub fn stake_cards(ctx: Context<StakeCard>, card_indices: Vec<u8>) -> Result<()> {
     let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
@@ -912,6 +1386,10 @@ pub fn unstake_card(ctx: Context<UnstakeCard>, card_index: u8) -> Result<()> {
     settle_and_mint_rewards(
         player,
         gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
         &ctx.accounts.token_mint.to_account_info(),
@@ -920,57 +1398,80 @@ pub fn unstake_card(ctx: Context<UnstakeCard>, card_index: u8) -> Result<()> {
         ctx.bumps.global_state,
     )?;
 
-    // Security: Validate card index bounds
-    validate_card_index(card_index, player.card_count as usize)?;
-
-    require!(
-        player.is_card_staked(card_index),
-        PonzimonError::CardNotStaked
-    );
+    // Pick up any rebalance of the player's farm level
+    player.sync_farm(&ctx.accounts.farm_config)?;
 
-    // Ensure the card is not currently being recycled
+    require!(!card_indices.is_empty(), PonzimonError::InvalidCardCount);
     require!(
-        !player.is_card_being_recycled(card_index),
-        PonzimonError::CardIsStaked // Reusing this error for consistency
+        player.count_staked_cards() as usize + card_indices.len()
+            <= player.farm.total_cards as usize,
+        PonzimonError::MachineCapacityExceeded
     );
 
-    let card = &player.cards[card_index as usize];
-    let card_berry_consumption = card.berry_consumption as u64;
-    let card_hashpower = card.hashpower as u64;
+    let mut berry_consumption = 0u64;
+    let mut hashpower = 0u64;
+    for (i, &card_index) in card_indices.iter().enumerate() {
+        validate_card_index(card_index, player.card_count as usize)?;
+        require!(
+            !card_indices[..i].contains(&card_index),
+            PonzimonError::DuplicateCardIndices
+        );
+        require!(
+            !player.is_card_staked(card_index),
+            PonzimonError::CardIsStaked
+        );
+        require!(
+            !player.is_card_being_recycled(card_index),
+            PonzimonError::CardPendingRecycling
+        );
+        let card = &player.cards[card_index as usize];
+        berry_consumption = safe_add_berries(berry_consumption, card.berry_consumption as u64)?;
+        hashpower = safe_add_hashpower(hashpower, card.hashpower as u64)?;
+    }
 
     // Security: Use safe arithmetic for berry and power calculations
-    let new_player_berries = safe_sub_berries(player.berries, card_berry_consumption)?;
-    let new_total_berries = safe_sub_berries(gs.total_berries, card_berry_consumption)?;
-    let new_player_hashpower = safe_sub_hashpower(player.total_hashpower, card_hashpower)?;
-    let new_total_hashpower = safe_sub_hashpower(gs.total_hashpower, card_hashpower)?;
+    let new_player_berries = safe_add_berries(player.berries, berry_consumption)?;
+    let new_total_berries = safe_add_berries(gs.total_berries, berry_consumption)?;
+    let new_player_hashpower = safe_add_hashpower(player.total_hashpower, hashpower)?;
+    let new_total_hashpower = safe_add_hashpower(gs.total_hashpower, hashpower)?;
+
+    require!(
+        new_player_berries <= player.farm.berry_capacity,
+        PonzimonError::PowerCapacityExceeded
+    );
 
     // Effects
-    player.unstake_card(card_index)?;
+    for &card_index in card_indices.iter() {
+        player.stake_card(card_index)?;
+    }
     player.berries = new_player_berries;
     player.total_hashpower = new_player_hashpower;
     gs.total_berries = new_total_berries;
     gs.total_hashpower = new_total_hashpower;
 
-    emit!(CardUnstaked {
+    emit!(CardsStaked {
         player: player.key(),
-        card_index,
+        card_serials: card_indices
+            .iter()
+            .map(|&index| player.cards[index as usize].serial)
+            .collect(),
+        card_indices,
     });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-///  UPGRADE FARM
+///  UNSTAKE CARD
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-#[instruction(farm_type: u8)]
-pub struct UpgradeFarm<'info> {
+#[instruction(card_index: u8)]
+pub struct UnstakeCard<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
-        constraint = player.farm.farm_type + 1 == farm_type  && (farm_type as usize) <= FARM_CONFIGS.len() -1 @ PonzimonError::InvalidFarmType,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -981,45 +1482,131 @@ pub struct UpgradeFarm<'info> {
         bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    /// Required while vesting is enabled
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    /// Receives the mining referral share; without it the share accrues on the player
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
     #[account(
         mut,
         seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
         bump,
     )]
     pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
     #[account(
         mut,
         constraint = player_token_account.mint == global_state.token_mint,
         constraint = player_token_account.owner == player_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
     )]
     pub player_token_account: Box<Account<'info, TokenAccount>>,
-    #[account(
-        mut,
-        constraint = fees_token_account.mint == global_state.token_mint,
-        constraint = fees_token_account.owner == global_state.fees_wallet @ PonzimonError::Unauthorized
-    )]
-    pub fees_token_account: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
 }
 
-pub fn upgrade_farm(ctx: Context<UpgradeFarm>, farm_type: u8) -> Result<()> {
+pub fn unstake_card(ctx: Context<UnstakeCard>, card_index: u8) -> Result<()> {
     let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
 
-    update_pool(gs, slot);
+    // Settle rewards before making changes
+    settle_and_mint_rewards(
+        player,
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.rewards_vault.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    // Security: Validate card index bounds
+    validate_card_index(card_index, player.card_count as usize)?;
 
-    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
     require!(
-        slot >= player.last_upgrade_slot + gs.cooldown_slots,
-        PonzimonError::CooldownNotExpired
+        player.is_card_staked(card_index),
+        PonzimonError::CardNotStaked
+    );
+
+    // Ensure the card is not currently being recycled
+    require!(
+        !player.is_card_being_recycled(card_index),
+        PonzimonError::CardIsStaked // Reusing this error for consistency
     );
 
+    let card = &player.cards[card_index as usize];
+    let card_berry_consumption = card.berry_consumption as u64;
+    let card_hashpower = card.hashpower as u64;
+
+    // Security: Use safe arithmetic for berry and power calculations
+    let new_player_berries = safe_sub_berries(player.berries, card_berry_consumption)?;
+    let new_total_berries = safe_sub_berries(gs.total_berries, card_berry_consumption)?;
+    let new_player_hashpower = safe_sub_hashpower(player.total_hashpower, card_hashpower)?;
+    let new_total_hashpower = safe_sub_hashpower(gs.total_hashpower, card_hashpower)?;
+
+    // Effects
+    player.unstake_card(card_index)?;
+    player.berries = new_player_berries;
+    player.total_hashpower = new_player_hashpower;
+    gs.total_berries = new_total_berries;
+    gs.total_hashpower = new_total_hashpower;
+
+    emit!(CardUnstaked {
+        player: player.key(),
+        card_index,
+        card_serial: player.cards[card_index as usize].serial,
+    });
+
+    Ok(())
+}
+
+/// Same as `unstake_card`, addressing the card by serial.
+pub fn unstake_card_by_serial(ctx: Context<UnstakeCard>, card_serial: u32) -> Result<()> {
+    let card_index = ctx.accounts.player.find_card_by_serial(card_serial)?;
+    unstake_card(ctx, card_index)
+}
+
+/// Unstakes several cards with a single reward settlement. All-or-nothing:
+/// every index must pass the same checks as `unstake_card`.
+pub fn unstake_cards(ctx: Context<UnstakeCard>, card_indices: Vec<u8>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    // Settle rewards before making changes
     settle_and_mint_rewards(
         player,
         gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
         &ctx.accounts.token_mint.to_account_info(),
@@ -1028,69 +1615,160 @@ pub fn upgrade_farm(ctx: Context<UpgradeFarm>, farm_type: u8) -> Result<()> {
         ctx.bumps.global_state,
     )?;
 
-    let (total_cards, berry_capacity, cost) = FARM_CONFIGS[farm_type as usize];
+    require!(!card_indices.is_empty(), PonzimonError::InvalidCardCount);
 
-    require!(
-        ctx.accounts.player_token_account.amount >= cost,
-        PonzimonError::InsufficientTokens
-    );
+    let mut berry_consumption = 0u64;
+    let mut hashpower = 0u64;
+    for (i, &card_index) in card_indices.iter().enumerate() {
+        validate_card_index(card_index, player.card_count as usize)?;
+        require!(
+            !card_indices[..i].contains(&card_index),
+            PonzimonError::DuplicateCardIndices
+        );
+        require!(
+            player.is_card_staked(card_index),
+            PonzimonError::CardNotStaked
+        );
+        require!(
+            !player.is_card_being_recycled(card_index),
+            PonzimonError::CardPendingRecycling
+        );
+        let card = &player.cards[card_index as usize];
+        berry_consumption = safe_add_berries(berry_consumption, card.berry_consumption as u64)?;
+        hashpower = safe_add_hashpower(hashpower, card.hashpower as u64)?;
+    }
 
-    let burn_amount = cost * gs.burn_rate as u64 / 100;
-    let fees_amount = cost - burn_amount;
+    // Security: Use safe arithmetic for berry and power calculations
+    let new_player_berries = safe_sub_berries(player.berries, berry_consumption)?;
+    let new_total_berries = safe_sub_berries(gs.total_berries, berry_consumption)?;
+    let new_player_hashpower = safe_sub_hashpower(player.total_hashpower, hashpower)?;
+    let new_total_hashpower = safe_sub_hashpower(gs.total_hashpower, hashpower)?;
 
-    // === EFFECTS ===
-    // Update player farm and state
-    player.farm.farm_type = farm_type;
-    player.farm.total_cards = total_cards;
-    player.farm.berry_capacity = berry_capacity;
-    player.last_upgrade_slot = slot;
-    player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
+    // Effects
+    for &card_index in card_indices.iter() {
+        player.unstake_card(card_index)?;
+    }
+    player.berries = new_player_berries;
+    player.total_hashpower = new_player_hashpower;
+    gs.total_berries = new_total_berries;
+    gs.total_hashpower = new_total_hashpower;
 
-    // Update global state for burned tokens
-    gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
+    emit!(CardsUnstaked {
+        player: player.key(),
+        card_serials: card_indices
+            .iter()
+            .map(|&index| player.cards[index as usize].serial)
+            .collect(),
+        card_indices,
+    });
 
-    // Update player spending tracking
-    player.total_tokens_spent = player.total_tokens_spent.saturating_add(cost);
+    Ok(())
+}
 
-    // === INTERACTIONS ===
-    // Burn tokens
-    token::burn(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Burn {
-                mint: ctx.accounts.token_mint.to_account_info(),
-                from: ctx.accounts.player_token_account.to_account_info(),
-                authority: ctx.accounts.player_wallet.to_account_info(),
-            },
-        ),
-        burn_amount,
+/// ────────────────────────────────────────────────────────────────────────────
+///  REARRANGE STAKING
+/// ────────────────────────────────────────────────────────────────────────────
+/// Re-stakes the player's inventory as the set of cards with the highest total
+/// hashpower that fits the farm's card slots and berry capacity, with a single
+/// reward settlement. Cards pending recycling are left untouched.
+pub fn rearrange_staking(ctx: Context<StakeCard>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    // Settle rewards before making changes
+    settle_and_mint_rewards(
+        player,
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.rewards_vault.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
     )?;
-    token::transfer(
-        CpiContext::new(
-            ctx.accounts.token_program.to_account_info(),
-            Transfer {
-                from: ctx.accounts.player_token_account.to_account_info(),
-                to: ctx.accounts.fees_token_account.to_account_info(),
-                authority: ctx.accounts.player_wallet.to_account_info(),
-            },
-        ),
-        fees_amount,
+
+    // Pick up any rebalance of the player's farm level
+    player.sync_farm(&ctx.accounts.farm_config)?;
+
+    let indices: Vec<u8> = (0..player.card_count)
+        .filter(|&index| !player.is_card_being_recycled(index))
+        .collect();
+    let candidates: Vec<StakingCandidate> = indices
+        .iter()
+        .map(|&index| {
+            let card = &player.cards[index as usize];
+            StakingCandidate {
+                hashpower: card.hashpower as u64,
+                berry_consumption: card.berry_consumption as u64,
+                staked: player.is_card_staked(index),
+            }
+        })
+        .collect();
+    let slots = player.farm.total_cards.min(MAX_STAKED_CARDS_PER_PLAYER);
+    let selected = optimal_staking(
+        &candidates,
+        slots as u64,
+        player.farm.berry_capacity,
+        MAX_STAKING_SEARCH_NODES,
+    )
+    .ok_or(PonzimonError::StakingSearchTooLarge)?;
+
+    let mut new_bitset = 0u128;
+    let mut berries = 0u64;
+    let mut hashpower = 0u64;
+    for &i in selected.iter() {
+        let index = indices[i];
+        let card = &player.cards[index as usize];
+        new_bitset |= 1u128 << index;
+        berries = safe_add_berries(berries, card.berry_consumption as u64)?;
+        hashpower = safe_add_hashpower(hashpower, card.hashpower as u64)?;
+    }
+
+    let staked_serials: Vec<u32> = (0..player.card_count)
+        .filter(|&index| new_bitset & (1u128 << index) != 0 && !player.is_card_staked(index))
+        .map(|index| player.cards[index as usize].serial)
+        .collect();
+    let unstaked_serials: Vec<u32> = (0..player.card_count)
+        .filter(|&index| new_bitset & (1u128 << index) == 0 && player.is_card_staked(index))
+        .map(|index| player.cards[index as usize].serial)
+        .collect();
+
+    // Security: Use safe arithmetic for berry and power calculations
+    let new_total_berries =
+        safe_add_berries(safe_sub_berries(gs.total_berries, player.berries)?, berries)?;
+    let new_total_hashpower = safe_add_hashpower(
+        safe_sub_hashpower(gs.total_hashpower, player.total_hashpower)?,
+        hashpower,
     )?;
 
-    emit!(FarmUpgraded {
-        player: ctx.accounts.player_wallet.key(),
-        new_farm_type: farm_type,
+    // Effects
+    player.staked_cards_bitset = new_bitset;
+    player.berries = berries;
+    player.total_hashpower = hashpower;
+    gs.total_berries = new_total_berries;
+    gs.total_hashpower = new_total_hashpower;
+
+    emit!(StakingRearranged {
+        player: player.key(),
+        staked_serials,
+        unstaked_serials,
+        total_hashpower: hashpower,
+        berries,
     });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-///  CLAIM REWARDS
+///  TRANSFER CARD
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct ClaimRewards<'info> {
-    #[account(mut)]
+pub struct SetAcceptCardTransfers<'info> {
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
@@ -1099,66 +1777,65 @@ pub struct ClaimRewards<'info> {
         bump
     )]
     pub player: Box<Account<'info, Player>>,
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Players only receive transferred cards after opting in, so nobody can fill
+/// another player's card slots uninvited
+pub fn set_accept_card_transfers(ctx: Context<SetAcceptCardTransfers>, accept: bool) -> Result<()> {
+    ctx.accounts.player.accepts_card_transfers = accept;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct TransferCard<'info> {
+    #[account(mut)]
+    pub sender_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = sender.owner == sender_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, sender_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub sender: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        constraint = recipient.key() != sender.key() @ PonzimonError::CannotTransferToSelf,
+        seeds = [PLAYER_SEED, recipient.owner.as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub recipient: Box<Account<'info, Player>>,
     #[account(
         mut,
         seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
         bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
     #[account(
         mut,
-        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
         bump,
     )]
-    pub rewards_vault: Account<'info, TokenAccount>,
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    /// Required while vesting is enabled
     #[account(
         mut,
-        constraint = player_token_account.owner == player_wallet.key(),
-        constraint = player_token_account.mint == global_state.token_mint
+        seeds = [VESTING_SEED, sender_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
     )]
-    pub player_token_account: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    pub token_mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
-}
-
-pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
-    let now = Clock::get()?.slot;
-
-    settle_and_mint_rewards(
-        &mut ctx.accounts.player,
-        &mut ctx.accounts.global_state,
-        now,
-        &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
-        &ctx.accounts.rewards_vault.to_account_info(),
-        &ctx.accounts.token_program.to_account_info(),
-        ctx.bumps.global_state,
-    )?;
-
-    Ok(())
-}
-
-/// OPEN BOOSTER PACK (Secure two-step)
-
-#[derive(Accounts)]
-pub struct RequestOpenBooster<'info> {
-    #[account(mut)]
-    pub player_wallet: Signer<'info>,
-    #[account(
-        mut,
-        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
-        constraint = player.pending_action == PendingRandomAction::None @ PonzimonError::BoosterAlreadyPending,
-        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
-        bump
-    )]
-    pub player: Box<Account<'info, Player>>,
+    pub sender_vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    /// Required while vesting is enabled
     #[account(
         mut,
-        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        seeds = [VESTING_SEED, recipient.owner.as_ref(), token_mint.key().as_ref()],
         bump,
     )]
-    pub global_state: Account<'info, GlobalState>,
+    pub recipient_vesting_account: Option<Box<Account<'info, VestingAccount>>>,
     #[account(
         mut,
         seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
@@ -1167,427 +1844,3434 @@ pub struct RequestOpenBooster<'info> {
     pub rewards_vault: Account<'info, TokenAccount>,
     #[account(
         mut,
-        constraint = player_token_account.mint == global_state.token_mint,
-        constraint = player_token_account.owner == player_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
+        constraint = sender_token_account.mint == global_state.token_mint,
+        constraint = sender_token_account.owner == sender_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
     )]
-    pub player_token_account: Box<Account<'info, TokenAccount>>,
+    pub sender_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = recipient_token_account.mint == global_state.token_mint,
+        constraint = recipient_token_account.owner == recipient.owner @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub recipient_token_account: Box<Account<'info, TokenAccount>>,
     #[account(
         mut,
         constraint = fees_token_account.mint == global_state.token_mint,
         constraint = fees_token_account.owner == global_state.fees_wallet @ PonzimonError::Unauthorized
     )]
     pub fees_token_account: Box<Account<'info, TokenAccount>>,
-    /// CHECK: This is the referrer's token account. Optional, but required if the player has a referrer.
-    #[account(mut)]
-    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
-    #[account(mut)]
+    /// Sender's referrer token account. Required if mining referrals are enabled and the sender has a referrer.
+    #[account(
+        mut,
+        constraint = sender_referrer_token_account.mint == token_mint.key() @ PonzimonError::InvalidTokenMint
+    )]
+    pub sender_referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    /// Sender's vault page, when the card is stored there
+    #[account(
+        mut,
+        seeds = [CARD_VAULT_SEED, sender.key().as_ref(), &[sender_vault.page]],
+        bump,
+    )]
+    pub sender_vault: Option<Box<Account<'info, CardVault>>>,
+    /// Recipient's referrer token account. Required if mining referrals are enabled and the recipient has a referrer.
+    #[account(
+        mut,
+        constraint = recipient_referrer_token_account.mint == token_mint.key() @ PonzimonError::InvalidTokenMint
+    )]
+    pub recipient_referrer_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
     pub token_mint: Account<'info, Mint>,
     pub token_program: Program<'info, Token>,
-    /// CHECK: The account's data is validated manually within the handler.
-    pub randomness_account_data: AccountInfo<'info>,
 }
 
-pub fn request_open_booster(ctx: Context<RequestOpenBooster>) -> Result<()> {
+#[event]
+pub struct CardTransferred {
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub sender_serial: u32,
+    pub card: Card, // As stored on the recipient, with its new serial
+    pub fee: u64,
+}
+
+/// Moves an unstaked card that is not pending recycle to another player, from
+/// the sender's inventory or from `sender_vault`. The card gets a fresh serial
+/// from the recipient. Both players' mining rewards are settled first.
+pub fn transfer_card(ctx: Context<TransferCard>, card_serial: u32) -> Result<()> {
     let slot = Clock::get()?.slot;
-    let player = &mut ctx.accounts.player;
+    let sender = &mut ctx.accounts.sender;
+    let recipient = &mut ctx.accounts.recipient;
     let gs = &mut ctx.accounts.global_state;
 
-    // Guards
     require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+
+    // None when the card is taken from the sender's vault page
+    let card_index = match ctx.accounts.sender_vault.as_ref() {
+        Some(vault) if vault.contains(card_serial) => {
+            require!(
+                !sender.is_vault_card_being_recycled(vault.page, card_serial),
+                PonzimonError::CardPendingRecycling
+            );
+            None
+        }
+        _ => {
+            let card_index = sender.find_card_by_serial(card_serial)?;
+            require!(
+                !sender.is_card_staked(card_index),
+                PonzimonError::CardIsStaked
+            );
+            require!(
+                !sender.is_card_being_recycled(card_index),
+                PonzimonError::CardPendingRecycling
+            );
+            Some(card_index)
+        }
+    };
     require!(
-        (player.card_count as usize) + 5 <= MAX_CARDS_PER_PLAYER as usize,
+        recipient.accepts_card_transfers,
+        PonzimonError::RecipientNotAcceptingTransfers
+    );
+    require!(
+        (recipient.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
         PonzimonError::MachineCapacityExceeded
     );
 
-    // Settle any pending rewards first
+    let fee = gs.card_transfer_fee;
+    require!(
+        ctx.accounts.sender_token_account.amount >= fee,
+        PonzimonError::InsufficientTokens
+    );
+
     settle_and_mint_rewards(
-        player,
+        sender,
         gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.sender_vesting_account.as_deref_mut(),
+        ctx.accounts.sender_referrer_token_account.as_deref(),
         slot,
-        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.sender_token_account.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.rewards_vault.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+    settle_and_mint_rewards(
+        recipient,
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.recipient_vesting_account.as_deref_mut(),
+        ctx.accounts.recipient_referrer_token_account.as_deref(),
+        slot,
+        &ctx.accounts.recipient_token_account.to_account_info(),
         &ctx.accounts.token_mint.to_account_info(),
         &ctx.accounts.rewards_vault.to_account_info(),
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
     )?;
 
-    // --- Token Fee, Burn, and Referral Logic ---
-    let booster_cost = gs.booster_pack_cost_microtokens;
-
-    // Verify the randomness account
-    if ctx.accounts.randomness_account_data.key() != player.randomness_account {
-        return Err(PonzimonError::InvalidRandomnessAccount.into());
-    }
-    // Validate Switchboard randomness account
-    let randomness_data =
-        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
-    if randomness_data.seed_slot != slot - 1 {
-        return Err(PonzimonError::RandomnessAlreadyRevealed.into());
-    }
+    // === EFFECTS ===
+    let card = match card_index {
+        Some(card_index) => {
+            let card = sender.cards[card_index as usize];
+            sender.remove_card(card_index)?;
+            card
+        }
+        None => ctx
+            .accounts
+            .sender_vault
+            .as_mut()
+            .ok_or(PonzimonError::CardVaultRequired)?
+            .take(card_serial)?,
+    };
+    let new_serial = recipient.add_card(card)?;
 
-    // Burn/transfer tokens for the pack
-    let burn_amount = booster_cost
-        .saturating_mul(gs.burn_rate as u64)
-        .saturating_div(100);
-    let fees_amount = booster_cost.saturating_sub(burn_amount);
+    let burn_amount = (fee as u128 * gs.burn_rate as u128 / 100) as u64;
+    let fees_amount = fee - burn_amount;
+    gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
+    sender.total_tokens_spent = sender.total_tokens_spent.saturating_add(fee);
 
-    // First, burn the designated amount from the player's account.
+    // === INTERACTIONS ===
     if burn_amount > 0 {
-        gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
         token::burn(
             CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
                 Burn {
                     mint: ctx.accounts.token_mint.to_account_info(),
-                    from: ctx.accounts.player_token_account.to_account_info(),
-                    authority: ctx.accounts.player_wallet.to_account_info(),
+                    from: ctx.accounts.sender_token_account.to_account_info(),
+                    authority: ctx.accounts.sender_wallet.to_account_info(),
                 },
             ),
             burn_amount,
         )?;
     }
+    if fees_amount > 0 {
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sender_token_account.to_account_info(),
+                    to: ctx.accounts.fees_token_account.to_account_info(),
+                    authority: ctx.accounts.sender_wallet.to_account_info(),
+                },
+            ),
+            fees_amount,
+        )?;
+    }
 
-    // Next, handle the fees, splitting them if a referrer exists.
-    if let Some(referrer) = player.referrer {
-        require!(
-            ctx.accounts.referrer_token_account.clone().unwrap().owner == referrer.key(),
-            PonzimonError::ReferrerAccountMissing
-        );
-        let referral_commission = fees_amount
-            .saturating_mul(gs.referral_fee as u64)
-            .saturating_div(100);
-        let protocol_fee = fees_amount.saturating_sub(referral_commission);
+    emit!(CardTransferred {
+        sender: sender.key(),
+        recipient: recipient.key(),
+        sender_serial: card_serial,
+        card: Card {
+            serial: new_serial,
+            ..card
+        },
+        fee,
+    });
 
-        // Transfer commission to the referrer.
-        if referral_commission > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.player_token_account.to_account_info(),
-                        to: ctx
-                            .accounts
-                            .referrer_token_account
-                            .clone()
-                            .unwrap()
-                            .to_account_info(),
-                        authority: ctx.accounts.player_wallet.to_account_info(),
-                    },
-                ),
-                referral_commission,
-            )?;
-            player.total_earnings_for_referrer = player
-                .total_earnings_for_referrer
-                .saturating_add(referral_commission);
-        }
+    Ok(())
+}
 
-        // Transfer the remaining fee to the protocol wallet.
-        if protocol_fee > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.player_token_account.to_account_info(),
-                        to: ctx.accounts.fees_token_account.to_account_info(),
-                        authority: ctx.accounts.player_wallet.to_account_info(),
-                    },
-                ),
-                protocol_fee,
-            )?;
-        }
-    } else {
-        // No referrer, so the entire fee amount goes to the protocol.
-        if fees_amount > 0 {
-            token::transfer(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.player_token_account.to_account_info(),
-                        to: ctx.accounts.fees_token_account.to_account_info(),
-                        authority: ctx.accounts.player_wallet.to_account_info(),
-                    },
-                ),
-                fees_amount,
-            )?;
-        }
-    }
+/// ────────────────────────────────────────────────────────────────────────────
+///  MARKETPLACE
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(card_serial: u32)]
+pub struct ListCard<'info> {
+    #[account(mut)]
+    pub seller_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = seller.owner == seller_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, seller_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub seller: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = seller_wallet,
+        space = 8  /* discriminator */
+        + 32 + 32  /* seller + token_mint */
+        + 15       /* card */
+        + 8 + 8,   /* price + created_slot */
+        seeds = [
+            LISTING_SEED,
+            seller_wallet.key().as_ref(),
+            token_mint.key().as_ref(),
+            &card_serial.to_le_bytes()
+        ],
+        bump
+    )]
+    pub listing: Box<Account<'info, Listing>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
 
-    // Set player state for settlement
-    player.pending_action = PendingRandomAction::Booster;
-    player.commit_slot = randomness_data.seed_slot;
+#[event]
+pub struct CardListed {
+    pub listing: Pubkey,
+    pub seller: Pubkey,
+    pub card: Card,
+    pub price: u64,
+}
 
-    // Update player spending tracking
-    player.total_tokens_spent = player.total_tokens_spent.saturating_add(booster_cost);
+/// Moves an unstaked card that is not pending recycle out of the seller's
+/// player and into a listing at a fixed price.
+pub fn list_card(ctx: Context<ListCard>, card_serial: u32, price: u64) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let seller = &mut ctx.accounts.seller;
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        PonzimonError::ProductionDisabled
+    );
+    require!(price > 0, PonzimonError::InvalidListingPrice);
+
+    let card_index = seller.find_card_by_serial(card_serial)?;
+    require!(
+        !seller.is_card_staked(card_index),
+        PonzimonError::CardIsStaked
+    );
+    require!(
+        !seller.is_card_being_recycled(card_index),
+        PonzimonError::CardPendingRecycling
+    );
+
+    let card = seller.cards[card_index as usize];
+    seller.remove_card(card_index)?;
+
+    let listing = &mut ctx.accounts.listing;
+    listing.seller = ctx.accounts.seller_wallet.key();
+    listing.token_mint = ctx.accounts.token_mint.key();
+    listing.card = card;
+    listing.price = price;
+    listing.created_slot = slot;
+
+    emit!(CardListed {
+        listing: listing.key(),
+        seller: listing.seller,
+        card,
+        price,
+    });
 
     Ok(())
 }
 
 #[derive(Accounts)]
-pub struct SettleOpenBooster<'info> {
+pub struct CancelListing<'info> {
+    #[account(mut)]
+    pub seller_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = seller.owner == seller_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, seller_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub seller: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        close = seller_wallet,
+        seeds = [
+            LISTING_SEED,
+            seller_wallet.key().as_ref(),
+            token_mint.key().as_ref(),
+            &listing.card.serial.to_le_bytes()
+        ],
+        bump
+    )]
+    pub listing: Box<Account<'info, Listing>>,
+    pub token_mint: Account<'info, Mint>,
+}
+
+#[event]
+pub struct ListingCancelled {
+    pub listing: Pubkey,
+    pub seller: Pubkey,
+    pub card: Card, // As returned to the seller, with its new serial
+}
+
+/// Returns a listed card to the seller, under a fresh serial, and closes the
+/// listing. Works while production is paused.
+pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+    let seller = &mut ctx.accounts.seller;
+    let card = ctx.accounts.listing.card;
+    let serial = seller.add_card(card)?;
+
+    emit!(ListingCancelled {
+        listing: ctx.accounts.listing.key(),
+        seller: ctx.accounts.seller_wallet.key(),
+        card: Card { serial, ..card },
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct BuyListing<'info> {
+    #[account(mut)]
+    pub buyer_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = buyer.owner == buyer_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, buyer_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub buyer: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        close = seller_wallet,
+        constraint = listing.seller != buyer_wallet.key() @ PonzimonError::CannotBuyOwnListing,
+        seeds = [
+            LISTING_SEED,
+            listing.seller.as_ref(),
+            token_mint.key().as_ref(),
+            &listing.card.serial.to_le_bytes()
+        ],
+        bump
+    )]
+    pub listing: Box<Account<'info, Listing>>,
+    /// CHECK: Receives the listing rent; checked against `listing.seller`
+    #[account(
+        mut,
+        constraint = seller_wallet.key() == listing.seller @ PonzimonError::Unauthorized
+    )]
+    pub seller_wallet: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = buyer_token_account.mint == global_state.token_mint,
+        constraint = buyer_token_account.owner == buyer_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub buyer_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == global_state.token_mint,
+        constraint = seller_token_account.owner == listing.seller @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub seller_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ PonzimonError::Unauthorized
+    )]
+    pub fees_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct ListingPurchased {
+    pub listing: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub card: Card, // As stored on the buyer, with its new serial
+    pub price: u64,
+    pub burned: u64,
+    pub protocol_fee: u64,
+}
+
+/// Buys a listed card at its price. The protocol's `marketplace_fee_bps` cut
+/// is split between burn and the fees account by `burn_rate`; the seller gets
+/// the rest. The card moves into the buyer's player under a fresh serial.
+pub fn buy_listing(ctx: Context<BuyListing>) -> Result<()> {
+    let buyer = &mut ctx.accounts.buyer;
+    let gs = &mut ctx.accounts.global_state;
+    let listing = &ctx.accounts.listing;
+    let price = listing.price;
+
+    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+    require!(
+        ctx.accounts.buyer_token_account.amount >= price,
+        PonzimonError::InsufficientTokens
+    );
+
+    let (burn_amount, fees_amount, seller_amount) =
+        split_sale_price(price, gs.marketplace_fee_bps, gs.burn_rate);
+
+    // === EFFECTS ===
+    let card = listing.card;
+    let serial = buyer.add_card(card)?;
+    buyer.total_tokens_spent = buyer.total_tokens_spent.saturating_add(price);
+    gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
+
+    // === INTERACTIONS ===
+    if burn_amount > 0 {
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    from: ctx.accounts.buyer_token_account.to_account_info(),
+                    authority: ctx.accounts.buyer_wallet.to_account_info(),
+                },
+            ),
+            burn_amount,
+        )?;
+    }
+    if fees_amount > 0 {
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.buyer_token_account.to_account_info(),
+                    to: ctx.accounts.fees_token_account.to_account_info(),
+                    authority: ctx.accounts.buyer_wallet.to_account_info(),
+                },
+            ),
+            fees_amount,
+        )?;
+    }
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer_token_account.to_account_info(),
+                to: ctx.accounts.seller_token_account.to_account_info(),
+                authority: ctx.accounts.buyer_wallet.to_account_info(),
+            },
+        ),
+        seller_amount,
+    )?;
+
+    emit!(ListingPurchased {
+        listing: listing.key(),
+        seller: listing.seller,
+        buyer: ctx.accounts.buyer_wallet.key(),
+        card: Card { serial, ..card },
+        price,
+        burned: burn_amount,
+        protocol_fee: fees_amount,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  SWAP OFFERS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(offer_id: u64)]
+pub struct CreateSwapOffer<'info> {
+    #[account(mut)]
+    pub maker_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = maker.owner == maker_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, maker_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub maker: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = maker_wallet,
+        space = 8  /* discriminator */
+        + 32 + 32  /* maker + token_mint */
+        + 8        /* offer_id */
+        + 4 + MAX_SWAP_CARDS * 15 /* offered_cards */
+        + 4 + MAX_SWAP_CARDS * 3  /* wanted */
+        + 8 + 8,   /* wanted_tokens + created_slot */
+        seeds = [
+            SWAP_OFFER_SEED,
+            maker_wallet.key().as_ref(),
+            token_mint.key().as_ref(),
+            &offer_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub swap_offer: Box<Account<'info, SwapOffer>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct SwapOfferCreated {
+    pub swap_offer: Pubkey,
+    pub maker: Pubkey,
+    pub offered_cards: Vec<Card>,
+    pub wanted: Vec<SwapWant>,
+    pub wanted_tokens: u64,
+}
+
+/// Escrows up to `MAX_SWAP_CARDS` of the maker's cards and records what the
+/// maker wants back: one card per `wanted` entry, matched by id or rarity,
+/// plus `wanted_tokens`.
+pub fn create_swap_offer(
+    ctx: Context<CreateSwapOffer>,
+    offer_id: u64,
+    card_serials: Vec<u32>,
+    wanted: Vec<SwapWant>,
+    wanted_tokens: u64,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        PonzimonError::ProductionDisabled
+    );
+    require!(
+        !card_serials.is_empty() && card_serials.len() <= MAX_SWAP_CARDS,
+        PonzimonError::InvalidSwapOffer
+    );
+    require!(
+        wanted.len() <= MAX_SWAP_CARDS && (!wanted.is_empty() || wanted_tokens > 0),
+        PonzimonError::InvalidSwapOffer
+    );
+
+    let offered_cards = ctx.accounts.maker.take_cards(&card_serials)?;
+
+    let offer = &mut ctx.accounts.swap_offer;
+    offer.maker = ctx.accounts.maker_wallet.key();
+    offer.token_mint = ctx.accounts.token_mint.key();
+    offer.offer_id = offer_id;
+    offer.offered_cards = offered_cards;
+    offer.wanted = wanted;
+    offer.wanted_tokens = wanted_tokens;
+    offer.created_slot = slot;
+
+    emit!(SwapOfferCreated {
+        swap_offer: offer.key(),
+        maker: offer.maker,
+        offered_cards: offer.offered_cards.clone(),
+        wanted: offer.wanted.clone(),
+        wanted_tokens,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelSwapOffer<'info> {
+    #[account(mut)]
+    pub maker_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = maker.owner == maker_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, maker_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub maker: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        close = maker_wallet,
+        seeds = [
+            SWAP_OFFER_SEED,
+            maker_wallet.key().as_ref(),
+            token_mint.key().as_ref(),
+            &swap_offer.offer_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub swap_offer: Box<Account<'info, SwapOffer>>,
+    pub token_mint: Account<'info, Mint>,
+}
+
+#[event]
+pub struct SwapOfferCancelled {
+    pub swap_offer: Pubkey,
+    pub maker: Pubkey,
+    pub returned_serials: Vec<u32>, // New serials of the cards returned to the maker
+}
+
+/// Returns the escrowed cards to the maker, under fresh serials, and closes
+/// the offer. Works while production is paused.
+pub fn cancel_swap_offer(ctx: Context<CancelSwapOffer>) -> Result<()> {
+    let maker = &mut ctx.accounts.maker;
+    let returned_serials = ctx
+        .accounts
+        .swap_offer
+        .offered_cards
+        .iter()
+        .map(|card| maker.add_card(*card))
+        .collect::<Result<Vec<u32>>>()?;
+
+    emit!(SwapOfferCancelled {
+        swap_offer: ctx.accounts.swap_offer.key(),
+        maker: ctx.accounts.maker_wallet.key(),
+        returned_serials,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct AcceptSwapOffer<'info> {
+    #[account(mut)]
+    pub taker_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = taker.owner == taker_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, taker_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub taker: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [PLAYER_SEED, swap_offer.maker.as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub maker: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        close = maker_wallet,
+        constraint = swap_offer.maker != taker_wallet.key() @ PonzimonError::CannotAcceptOwnSwap,
+        seeds = [
+            SWAP_OFFER_SEED,
+            swap_offer.maker.as_ref(),
+            token_mint.key().as_ref(),
+            &swap_offer.offer_id.to_le_bytes()
+        ],
+        bump
+    )]
+    pub swap_offer: Box<Account<'info, SwapOffer>>,
+    /// CHECK: Receives the offer rent; checked against `swap_offer.maker`
+    #[account(
+        mut,
+        constraint = maker_wallet.key() == swap_offer.maker @ PonzimonError::Unauthorized
+    )]
+    pub maker_wallet: AccountInfo<'info>,
+    /// Required when the offer asks for tokens
+    #[account(
+        mut,
+        constraint = taker_token_account.mint == global_state.token_mint,
+        constraint = taker_token_account.owner == taker_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub taker_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    /// Required when the offer asks for tokens
+    #[account(
+        mut,
+        constraint = maker_token_account.mint == global_state.token_mint,
+        constraint = maker_token_account.owner == swap_offer.maker @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub maker_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    /// Required when the offer asks for tokens
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ PonzimonError::Unauthorized
+    )]
+    pub fees_token_account: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(
+        mut,
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct SwapOfferAccepted {
+    pub swap_offer: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub maker_received: Vec<Card>, // As stored on the maker, with new serials
+    pub taker_received: Vec<Card>, // As stored on the taker, with new serials
+    pub tokens_paid: u64,
+}
+
+/// Fills a swap offer. `card_serials` are the taker's cards, one per `wanted`
+/// entry and in the same order. Any tokens asked for are split like a
+/// marketplace sale.
+pub fn accept_swap_offer(ctx: Context<AcceptSwapOffer>, card_serials: Vec<u32>) -> Result<()> {
+    let taker = &mut ctx.accounts.taker;
+    let maker = &mut ctx.accounts.maker;
+    let gs = &mut ctx.accounts.global_state;
+    let offer = &ctx.accounts.swap_offer;
+
+    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+    require!(
+        card_serials.len() == offer.wanted.len(),
+        PonzimonError::SwapTermsNotMet
+    );
+
+    // === EFFECTS ===
+    let given_cards = taker.take_cards(&card_serials)?;
+    require!(
+        offer
+            .wanted
+            .iter()
+            .zip(given_cards.iter())
+            .all(|(want, card)| want.matches(card)),
+        PonzimonError::SwapTermsNotMet
+    );
+
+    let mut maker_received = Vec::with_capacity(given_cards.len());
+    for card in given_cards {
+        let serial = maker.add_card(card)?;
+        maker_received.push(Card { serial, ..card });
+    }
+    let mut taker_received = Vec::with_capacity(offer.offered_cards.len());
+    for &card in offer.offered_cards.iter() {
+        let serial = taker.add_card(card)?;
+        taker_received.push(Card { serial, ..card });
+    }
+
+    // === INTERACTIONS ===
+    let price = offer.wanted_tokens;
+    if price > 0 {
+        let taker_token_account = ctx
+            .accounts
+            .taker_token_account
+            .as_ref()
+            .ok_or(PonzimonError::SwapTokenAccountsMissing)?;
+        let maker_token_account = ctx
+            .accounts
+            .maker_token_account
+            .as_ref()
+            .ok_or(PonzimonError::SwapTokenAccountsMissing)?;
+        let fees_token_account = ctx
+            .accounts
+            .fees_token_account
+            .as_ref()
+            .ok_or(PonzimonError::SwapTokenAccountsMissing)?;
+        require!(
+            taker_token_account.amount >= price,
+            PonzimonError::InsufficientTokens
+        );
+
+        let (burn_amount, fees_amount, maker_amount) =
+            split_sale_price(price, gs.marketplace_fee_bps, gs.burn_rate);
+        gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
+        taker.total_tokens_spent = taker.total_tokens_spent.saturating_add(price);
+
+        if burn_amount > 0 {
+            token::burn(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Burn {
+                        mint: ctx.accounts.token_mint.to_account_info(),
+                        from: taker_token_account.to_account_info(),
+                        authority: ctx.accounts.taker_wallet.to_account_info(),
+                    },
+                ),
+                burn_amount,
+            )?;
+        }
+        if fees_amount > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: taker_token_account.to_account_info(),
+                        to: fees_token_account.to_account_info(),
+                        authority: ctx.accounts.taker_wallet.to_account_info(),
+                    },
+                ),
+                fees_amount,
+            )?;
+        }
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: taker_token_account.to_account_info(),
+                    to: maker_token_account.to_account_info(),
+                    authority: ctx.accounts.taker_wallet.to_account_info(),
+                },
+            ),
+            maker_amount,
+        )?;
+    }
+
+    emit!(SwapOfferAccepted {
+        swap_offer: offer.key(),
+        maker: offer.maker,
+        taker: ctx.accounts.taker_wallet.key(),
+        maker_received,
+        taker_received,
+        tokens_paid: price,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  CARD NFTS (export / import)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(card_serial: u32)]
+pub struct ExportCard<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    /// Derived from the card's catalog id and its serial; the player and serial
+    /// identify the card for good, since serials are never reused
+    #[account(
+        init,
+        payer = player_wallet,
+        mint::decimals = 0,
+        mint::authority = global_state,
+        seeds = [
+            CARD_MINT_SEED,
+            player.key().as_ref(),
+            &card_serial.to_le_bytes(),
+            &player.card_id_by_serial(card_serial).to_le_bytes()
+        ],
+        bump
+    )]
+    pub card_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer = player_wallet,
+        space = 8  /* discriminator */
+        + 32 + 32  /* card_mint + token_mint */
+        + 15       /* card */
+        + 32 + 8,  /* exported_by + exported_slot */
+        seeds = [CARD_MINT_RECORD_SEED, card_mint.key().as_ref()],
+        bump
+    )]
+    pub card_mint_record: Box<Account<'info, CardMintRecord>>,
+    #[account(
+        init,
+        payer = player_wallet,
+        associated_token::mint = card_mint,
+        associated_token::authority = player_wallet,
+    )]
+    pub card_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct CardExported {
+    pub player: Pubkey,
+    pub card_mint: Pubkey,
+    pub card: Card,
+}
+
+/// Takes an unstaked card that is not pending recycle out of the player and
+/// mints a single token for it to the player's wallet. Mint authority is
+/// dropped afterwards, so the supply stays at one.
+pub fn export_card(ctx: Context<ExportCard>, card_serial: u32) -> Result<()> {
+    let slot = Clock::get()?.slot;
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        PonzimonError::ProductionDisabled
+    );
+
+    let card = ctx.accounts.player.take_cards(&[card_serial])?[0];
+
+    let record = &mut ctx.accounts.card_mint_record;
+    record.card_mint = ctx.accounts.card_mint.key();
+    record.token_mint = ctx.accounts.token_mint.key();
+    record.card = card;
+    record.exported_by = ctx.accounts.player_wallet.key();
+    record.exported_slot = slot;
+
+    let token_mint_key = ctx.accounts.token_mint.key();
+    let seeds = &[
+        GLOBAL_STATE_SEED,
+        token_mint_key.as_ref(),
+        &[ctx.bumps.global_state],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::mint_to(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.card_mint.to_account_info(),
+                to: ctx.accounts.card_token_account.to_account_info(),
+                authority: ctx.accounts.global_state.to_account_info(),
+            },
+            signer,
+        ),
+        1,
+    )?;
+    token::set_authority(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::SetAuthority {
+                current_authority: ctx.accounts.global_state.to_account_info(),
+                account_or_mint: ctx.accounts.card_mint.to_account_info(),
+            },
+            signer,
+        ),
+        token::spl_token::instruction::AuthorityType::MintTokens,
+        None,
+    )?;
+
+    emit!(CardExported {
+        player: ctx.accounts.player.key(),
+        card_mint: record.card_mint,
+        card,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ImportCard<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(mut)]
+    pub card_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        close = exporter_wallet,
+        constraint = card_mint_record.token_mint == token_mint.key() @ PonzimonError::InvalidTokenMint,
+        seeds = [CARD_MINT_RECORD_SEED, card_mint.key().as_ref()],
+        bump
+    )]
+    pub card_mint_record: Box<Account<'info, CardMintRecord>>,
+    /// CHECK: Receives the record rent; checked against `card_mint_record.exported_by`
+    #[account(
+        mut,
+        constraint = exporter_wallet.key() == card_mint_record.exported_by @ PonzimonError::Unauthorized
+    )]
+    pub exporter_wallet: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = card_token_account.mint == card_mint.key() @ PonzimonError::InvalidTokenMint,
+        constraint = card_token_account.owner == player_wallet.key() @ PonzimonError::InvalidTokenAccountOwner,
+        constraint = card_token_account.amount == 1 @ PonzimonError::InsufficientTokens
+    )]
+    pub card_token_account: Box<Account<'info, TokenAccount>>,
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct CardImported {
+    pub player: Pubkey,
+    pub card_mint: Pubkey,
+    pub card: Card, // As stored on the player, with its new serial
+}
+
+/// Burns a card token and restores the card into the holder's player under a
+/// fresh serial. The holder's token account and the mint record are closed.
+pub fn import_card(ctx: Context<ImportCard>) -> Result<()> {
+    let card = ctx.accounts.card_mint_record.card;
+    let serial = ctx.accounts.player.add_card(card)?;
+
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.card_mint.to_account_info(),
+                from: ctx.accounts.card_token_account.to_account_info(),
+                authority: ctx.accounts.player_wallet.to_account_info(),
+            },
+        ),
+        1,
+    )?;
+    token::close_account(CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        token::CloseAccount {
+            account: ctx.accounts.card_token_account.to_account_info(),
+            destination: ctx.accounts.player_wallet.to_account_info(),
+            authority: ctx.accounts.player_wallet.to_account_info(),
+        },
+    ))?;
+
+    emit!(CardImported {
+        player: ctx.accounts.player.key(),
+        card_mint: ctx.accounts.card_mint.key(),
+        card: Card { serial, ..card },
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  CARD VAULT (overflow storage)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(page: u8)]
+pub struct DepositToVault<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8  /* discriminator */
+        + 32 + 1   /* player + page */
+        + 4 + CARD_VAULT_PAGE_SIZE * 15, /* cards */
+        seeds = [CARD_VAULT_SEED, player.key().as_ref(), &[page]],
+        bump
+    )]
+    pub card_vault: Box<Account<'info, CardVault>>,
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct VaultCardsMoved {
+    pub player: Pubkey,
+    pub page: u8,
+    pub card_serials: Vec<u32>,
+    pub deposited: bool, // false for withdrawals
+}
+
+/// Moves unstaked cards that are not pending recycle from the inventory into
+/// a vault page, creating the page on first use. Serials are kept.
+pub fn deposit_to_vault(
+    ctx: Context<DepositToVault>,
+    page: u8,
+    card_serials: Vec<u32>,
+) -> Result<()> {
+    require!(page < MAX_CARD_VAULT_PAGES, PonzimonError::InvalidVaultPage);
+
+    let player = &mut ctx.accounts.player;
+    let vault = &mut ctx.accounts.card_vault;
+    vault.player = player.key();
+    vault.page = page;
+    require!(
+        vault.cards.len() + card_serials.len() <= CARD_VAULT_PAGE_SIZE,
+        PonzimonError::CardVaultFull
+    );
+
+    for card in player.take_cards(&card_serials)? {
+        vault.push(card)?;
+    }
+
+    emit!(VaultCardsMoved {
+        player: player.key(),
+        page,
+        card_serials,
+        deposited: true,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct WithdrawFromVault<'info> {
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [CARD_VAULT_SEED, player.key().as_ref(), &[card_vault.page]],
+        bump
+    )]
+    pub card_vault: Box<Account<'info, CardVault>>,
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Moves cards from a vault page back into the inventory, keeping serials.
+pub fn withdraw_from_vault(ctx: Context<WithdrawFromVault>, card_serials: Vec<u32>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+    let vault = &mut ctx.accounts.card_vault;
+
+    for &serial in card_serials.iter() {
+        require!(
+            !player.is_vault_card_being_recycled(vault.page, serial),
+            PonzimonError::CardPendingRecycling
+        );
+        player.push_card(vault.take(serial)?)?;
+    }
+
+    emit!(VaultCardsMoved {
+        player: player.key(),
+        page: vault.page,
+        card_serials,
+        deposited: false,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  UPGRADE FARM
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(farm_type: u8)]
+pub struct UpgradeFarm<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        constraint = player.farm.farm_type + 1 == farm_type && (farm_type as usize) < farm_config.levels.len() @ PonzimonError::InvalidFarmType,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        seeds = [FARM_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub farm_config: Box<Account<'info, FarmConfig>>,
+    /// Required while vesting is enabled
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    /// Receives the mining referral share; without it the share accrues on the player
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ PonzimonError::Unauthorized
+    )]
+    pub fees_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn upgrade_farm(ctx: Context<UpgradeFarm>, farm_type: u8) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        slot,
+    );
+
+    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+    require!(
+        slot >= player.last_upgrade_slot + gs.cooldown_slots,
+        PonzimonError::CooldownNotExpired
+    );
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.rewards_vault.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    let cost = ctx.accounts.farm_config.level(farm_type)?.cost;
+
+    require!(
+        ctx.accounts.player_token_account.amount >= cost,
+        PonzimonError::InsufficientTokens
+    );
+
+    let burn_amount = cost * gs.burn_rate as u64 / 100;
+    let fees_amount = cost - burn_amount;
+
+    // === EFFECTS ===
+    // Update player farm and state
+    player.farm = ctx.accounts.farm_config.farm(farm_type)?;
+    player.last_upgrade_slot = slot;
+    player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
+
+    // Update global state for burned tokens
+    gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
+
+    // Update player spending tracking
+    player.total_tokens_spent = player.total_tokens_spent.saturating_add(cost);
+
+    // === INTERACTIONS ===
+    // Burn tokens
+    token::burn(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Burn {
+                mint: ctx.accounts.token_mint.to_account_info(),
+                from: ctx.accounts.player_token_account.to_account_info(),
+                authority: ctx.accounts.player_wallet.to_account_info(),
+            },
+        ),
+        burn_amount,
+    )?;
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.player_token_account.to_account_info(),
+                to: ctx.accounts.fees_token_account.to_account_info(),
+                authority: ctx.accounts.player_wallet.to_account_info(),
+            },
+        ),
+        fees_amount,
+    )?;
+
+    emit!(FarmUpgraded {
+        player: ctx.accounts.player_wallet.key(),
+        new_farm_type: farm_type,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  CLAIM REWARDS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    /// Required while vesting is enabled
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    /// Receives the mining referral share; without it the share accrues on the player
+    #[account(
+        mut,
+        constraint = referrer_token_account.mint == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.owner == player_wallet.key(),
+        constraint = player_token_account.mint == global_state.token_mint
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+    let now = Clock::get()?.slot;
+
+    settle_and_mint_rewards(
+        &mut ctx.accounts.player,
+        &mut ctx.accounts.global_state,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
+        now,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.rewards_vault.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  REFERRAL CODES
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(code: String)]
+pub struct RegisterReferralCode<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8  /* discriminator */
+        + 32 + 32  /* owner + token_mint */
+        + 4 + MAX_REFERRAL_CODE_LEN /* code: String */
+        + 8,       /* created_slot */
+        seeds = [REFERRAL_CODE_SEED, token_mint.key().as_ref(), code.as_bytes()],
+        bump
+    )]
+    pub referral_code: Box<Account<'info, ReferralCode>>,
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Claims a referral code for the signer. The PDA is seeded by the code, so
+/// each code can only be held by one wallet at a time.
+pub fn register_referral_code(ctx: Context<RegisterReferralCode>, code: String) -> Result<()> {
+    validate_referral_code(&code)?;
+
+    let referral_code = &mut ctx.accounts.referral_code;
+    referral_code.owner = ctx.accounts.owner.key();
+    referral_code.token_mint = ctx.accounts.token_mint.key();
+    referral_code.code = code;
+    referral_code.created_slot = Clock::get()?.slot;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ReleaseReferralCode<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ PonzimonError::Unauthorized,
+        seeds = [REFERRAL_CODE_SEED, token_mint.key().as_ref(), referral_code.code.as_bytes()],
+        bump
+    )]
+    pub referral_code: Box<Account<'info, ReferralCode>>,
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Gives up a referral code and refunds its rent. Players already referred
+/// through it keep their referrer.
+pub fn release_referral_code(_ctx: Context<ReleaseReferralCode>) -> Result<()> {
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  CLAIM DELEGATION (keeper claims)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SetClaimDelegation<'info> {
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    /// Token account keeper claims are paid into; omit to clear the delegation
+    #[account(
+        constraint = claim_destination.mint == token_mint.key() @ PonzimonError::InvalidTokenMint
+    )]
+    pub claim_destination: Option<Account<'info, TokenAccount>>,
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Opts the player in to keeper claims. A keeper can only send rewards to the
+/// destination chosen here; passing no destination clears both settings.
+pub fn set_claim_delegation(
+    ctx: Context<SetClaimDelegation>,
+    keeper: Option<Pubkey>,
+) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+    match &ctx.accounts.claim_destination {
+        Some(destination) => {
+            player.claim_destination = Some(destination.key());
+            player.claim_keeper = keeper;
+        }
+        None => {
+            player.claim_destination = None;
+            player.claim_keeper = None;
+        }
+    }
+    Ok(())
+}
+
+#[event]
+pub struct KeeperClaim {
+    pub player: Pubkey,
+    pub keeper: Pubkey,
+    pub claim_destination: Pubkey,
+    pub amount: u64,
+}
+
+#[derive(Accounts)]
+pub struct KeeperClaimRewards<'info> {
+    pub keeper: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Claims mining rewards for every player listed in `remaining_accounts`. Each
+/// player is given as `player, claim_destination`, followed by the player's
+/// vesting account while vesting is enabled, and by the referrer's token account
+/// when the player has a referrer and mining referrals are enabled. Each player
+/// must have named the signer as keeper, and funds only go to the destination
+/// the player chose. Players that already claimed this slot are skipped so one
+/// manual claim cannot fail the batch.
+pub fn keeper_claim_rewards<'info>(
+    ctx: Context<'_, '_, 'info, 'info, KeeperClaimRewards<'info>>,
+) -> Result<()> {
+    let now = Clock::get()?.slot;
+    let keeper = ctx.accounts.keeper.key();
+    let token_mint_key = ctx.accounts.token_mint.key();
+    let vesting_enabled = ctx.accounts.global_state.vesting_percent > 0;
+    let mining_referrals_enabled = ctx.accounts.global_state.mining_referral_bps > 0;
+
+    require!(
+        !ctx.remaining_accounts.is_empty(),
+        PonzimonError::InvalidRemainingAccounts
+    );
+    let mut accounts = ctx.remaining_accounts.iter();
+
+    while let Some(player_info) = accounts.next() {
+        let destination_info = accounts
+            .next()
+            .ok_or(PonzimonError::InvalidRemainingAccounts)?;
+
+        let mut player = Box::new(Account::<Player>::try_from(player_info)?);
+        let (expected_player, _) = Pubkey::find_program_address(
+            &[PLAYER_SEED, player.owner.as_ref(), token_mint_key.as_ref()],
+            ctx.program_id,
+        );
+        require_keys_eq!(
+            player_info.key(),
+            expected_player,
+            PonzimonError::InvalidPlayerAccount
+        );
+        require!(
+            player.claim_keeper == Some(keeper),
+            PonzimonError::Unauthorized
+        );
+        require!(
+            player.claim_destination == Some(destination_info.key()),
+            PonzimonError::InvalidClaimDestination
+        );
+        let destination = Account::<TokenAccount>::try_from(destination_info)?;
+        require_keys_eq!(
+            destination.mint,
+            token_mint_key,
+            PonzimonError::InvalidTokenMint
+        );
+
+        let mut vesting_account = if vesting_enabled {
+            let vesting_info = accounts
+                .next()
+                .ok_or(PonzimonError::InvalidRemainingAccounts)?;
+            let (expected_vesting, _) = Pubkey::find_program_address(
+                &[VESTING_SEED, player.owner.as_ref(), token_mint_key.as_ref()],
+                ctx.program_id,
+            );
+            require_keys_eq!(
+                vesting_info.key(),
+                expected_vesting,
+                PonzimonError::VestingAccountRequired
+            );
+            Some(Account::<VestingAccount>::try_from(vesting_info)?)
+        } else {
+            None
+        };
+
+        // Owner and mint are checked against `player.referrer` during settlement.
+        // A closed or foreign account only defers the referral share.
+        let referrer_token_account = if mining_referrals_enabled && player.referrer.is_some() {
+            let referrer_info = accounts
+                .next()
+                .ok_or(PonzimonError::InvalidRemainingAccounts)?;
+            Account::<TokenAccount>::try_from(referrer_info).ok()
+        } else {
+            None
+        };
+
+        if now <= player.last_claim_slot {
+            continue;
+        }
+
+        let amount = settle_and_mint_rewards(
+            &mut player,
+            &mut ctx.accounts.global_state,
+            &ctx.accounts.emission_schedule,
+            &mut ctx.accounts.rate_history,
+            vesting_account.as_mut(),
+            referrer_token_account.as_ref(),
+            now,
+            destination_info,
+            &ctx.accounts.token_mint.to_account_info(),
+            &ctx.accounts.rewards_vault.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            ctx.bumps.global_state,
+        )?;
+        player.exit(ctx.program_id)?;
+        if let Some(vesting_account) = &vesting_account {
+            vesting_account.exit(ctx.program_id)?;
+        }
+
+        emit!(KeeperClaim {
+            player: player_info.key(),
+            keeper,
+            claim_destination: destination_info.key(),
+            amount,
+        });
+    }
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  VESTING
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct InitializeVestingAccount<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        init,
+        payer = player_wallet,
+        space = 8  /* discriminator */
+        + 32       /* owner */
+        + 8 + 8    /* locked_amount + unlocked_amount */
+        + 8 + 8    /* last_update_slot + end_slot */
+        + 8 + 8,   /* total_vested + total_withdrawn */
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub vesting_account: Box<Account<'info, VestingAccount>>,
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_vesting_account(ctx: Context<InitializeVestingAccount>) -> Result<()> {
+    let vesting = &mut ctx.accounts.vesting_account;
+    vesting.owner = ctx.accounts.player_wallet.key();
+    vesting.locked_amount = 0;
+    vesting.unlocked_amount = 0;
+    vesting.last_update_slot = Clock::get()?.slot;
+    vesting.end_slot = vesting.last_update_slot;
+    vesting.total_vested = 0;
+    vesting.total_withdrawn = 0;
+    Ok(())
+}
+
+#[event]
+pub struct VestedWithdrawn {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub still_locked: u64,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawVested<'info> {
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = vesting_account.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub vesting_account: Box<Account<'info, VestingAccount>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.owner == player_wallet.key(),
+        constraint = player_token_account.mint == global_state.token_mint
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Releases the part of the player's vested mining rewards that has unlocked.
+pub fn withdraw_vested(ctx: Context<WithdrawVested>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let vesting = &mut ctx.accounts.vesting_account;
+    let gs = &mut ctx.accounts.global_state;
+
+    let amount = vesting.withdraw(slot);
+    require!(amount > 0, PonzimonError::NoVestedTokens);
+
+    // Vested tokens count as claimed once they leave the vault
+    gs.total_rewards_claimed = gs.total_rewards_claimed.saturating_add(amount);
+
+    let token_mint_key = ctx.accounts.token_mint.key();
+    let seeds = &[
+        GLOBAL_STATE_SEED,
+        token_mint_key.as_ref(),
+        &[ctx.bumps.global_state],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.rewards_vault.to_account_info(),
+                to: ctx.accounts.player_token_account.to_account_info(),
+                authority: gs.to_account_info(),
+            },
+            signer,
+        ),
+        amount,
+    )?;
+
+    emit!(VestedWithdrawn {
+        player: ctx.accounts.player_wallet.key(),
+        amount,
+        still_locked: vesting.locked_amount,
+    });
+
+    Ok(())
+}
+
+/// OPEN BOOSTER PACK (Secure two-step)
+
+#[derive(Accounts)]
+pub struct RequestOpenBooster<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ PonzimonError::BoosterAlreadyPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    /// Required while vesting is enabled
+    #[account(
+        mut,
+        seeds = [VESTING_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump,
+    )]
+    pub vesting_account: Option<Box<Account<'info, VestingAccount>>>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ PonzimonError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ PonzimonError::Unauthorized
+    )]
+    pub fees_token_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: This is the referrer's token account. Optional, but required if the player has a referrer.
+    #[account(mut)]
+    pub referrer_token_account: Option<Account<'info, TokenAccount>>,
+    /// Referrer stats. Required if the player has a referrer.
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8  /* discriminator */
+        + 32 + 32  /* wallet + token_mint */
+        + 8        /* referee_count */
+        + 8 + 8    /* total_sol_earned + booster_tokens_earned */
+        + 8,       /* last_referral_slot */
+        seeds = [
+            REFERRER_SEED,
+            player.referrer.unwrap_or_default().as_ref(),
+            token_mint.key().as_ref()
+        ],
+        bump
+    )]
+    pub referrer_account: Option<Box<Account<'info, Referrer>>>,
+    #[account(
+        seeds = [REFERRAL_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub referral_config: Box<Account<'info, ReferralConfig>>,
+    #[account(mut)]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: The account's data is validated manually within the handler.
+    pub randomness_account_data: AccountInfo<'info>,
+}
+
+pub fn request_open_booster<'info>(
+    ctx: Context<'_, '_, '_, 'info, RequestOpenBooster<'info>>,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    // Guards
+    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+    require!(
+        (player.card_count as usize) + 5 <= MAX_CARDS_PER_PLAYER as usize,
+        PonzimonError::MachineCapacityExceeded
+    );
+
+    // Settle any pending rewards first
+    settle_and_mint_rewards(
+        player,
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        ctx.accounts.vesting_account.as_deref_mut(),
+        ctx.accounts.referrer_token_account.as_ref(),
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.rewards_vault.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    // --- Token Fee, Burn, and Referral Logic ---
+    let booster_cost = gs.booster_pack_cost_microtokens;
+
+    // Verify the randomness account
+    if ctx.accounts.randomness_account_data.key() != player.randomness_account {
+        return Err(PonzimonError::InvalidRandomnessAccount.into());
+    }
+    // Validate Switchboard randomness account
+    let randomness_data =
+        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
+    if randomness_data.seed_slot != slot - 1 {
+        return Err(PonzimonError::RandomnessAlreadyRevealed.into());
+    }
+
+    // Burn/transfer tokens for the pack
+    let burn_amount = booster_cost
+        .saturating_mul(gs.burn_rate as u64)
+        .saturating_div(100);
+    let fees_amount = booster_cost.saturating_sub(burn_amount);
+
+    // First, burn the designated amount from the player's account.
+    if burn_amount > 0 {
+        gs.burned_tokens = gs.burned_tokens.saturating_add(burn_amount);
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    from: ctx.accounts.player_token_account.to_account_info(),
+                    authority: ctx.accounts.player_wallet.to_account_info(),
+                },
+            ),
+            burn_amount,
+        )?;
+    }
+
+    // Next, handle the fees, splitting them if a referrer exists.
+    if let Some(referrer) = player.referrer {
+        require!(
+            ctx.accounts.referrer_token_account.clone().unwrap().owner == referrer.key(),
+            PonzimonError::ReferrerAccountMissing
+        );
+        let referral_config = &ctx.accounts.referral_config;
+        let referral_commission = referral_config.level_share(fees_amount, 0, gs.referral_fee);
+        let mut protocol_fee = fees_amount.saturating_sub(referral_commission);
+
+        // Transfer commission to the referrer.
+        if referral_commission > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.player_token_account.to_account_info(),
+                        to: ctx
+                            .accounts
+                            .referrer_token_account
+                            .clone()
+                            .unwrap()
+                            .to_account_info(),
+                        authority: ctx.accounts.player_wallet.to_account_info(),
+                    },
+                ),
+                referral_commission,
+            )?;
+            player.total_earnings_for_referrer = player
+                .total_earnings_for_referrer
+                .saturating_add(referral_commission);
+        }
+
+        let referrer_account = ctx
+            .accounts
+            .referrer_account
+            .as_mut()
+            .ok_or(PonzimonError::ReferrerAccountMissing)?;
+        referrer_account.wallet = referrer;
+        referrer_account.token_mint = ctx.accounts.token_mint.key();
+        referrer_account.booster_tokens_earned = referrer_account
+            .booster_tokens_earned
+            .saturating_add(referral_commission);
+
+        // Pay the referrers further up the chain, each at their level's share.
+        let uplines = resolve_referral_chain(
+            player.owner,
+            referrer,
+            referral_config.depth(),
+            ctx.accounts.token_mint.key(),
+            ctx.program_id,
+            ctx.remaining_accounts,
+        )?;
+        for (level, (upline, recipient)) in uplines.into_iter().enumerate() {
+            let recipient_token_account =
+                TokenAccount::try_deserialize(&mut &recipient.data.borrow()[..])?;
+            require!(
+                recipient_token_account.owner == upline
+                    && recipient_token_account.mint == gs.token_mint,
+                PonzimonError::ReferrerAccountMissing
+            );
+            let share = referral_config.level_share(fees_amount, level + 1, gs.referral_fee);
+            if share == 0 {
+                continue;
+            }
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.player_token_account.to_account_info(),
+                        to: recipient.clone(),
+                        authority: ctx.accounts.player_wallet.to_account_info(),
+                    },
+                ),
+                share,
+            )?;
+            protocol_fee = protocol_fee.saturating_sub(share);
+        }
+
+        // Transfer the remaining fee to the protocol wallet.
+        if protocol_fee > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.player_token_account.to_account_info(),
+                        to: ctx.accounts.fees_token_account.to_account_info(),
+                        authority: ctx.accounts.player_wallet.to_account_info(),
+                    },
+                ),
+                protocol_fee,
+            )?;
+        }
+    } else {
+        // No referrer, so the entire fee amount goes to the protocol.
+        if fees_amount > 0 {
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.player_token_account.to_account_info(),
+                        to: ctx.accounts.fees_token_account.to_account_info(),
+                        authority: ctx.accounts.player_wallet.to_account_info(),
+                    },
+                ),
+                fees_amount,
+            )?;
+        }
+    }
+
+    // Set player state for settlement
+    player.pending_action = PendingRandomAction::Booster;
+    player.commit_slot = randomness_data.seed_slot;
+
+    // Update player spending tracking
+    player.total_tokens_spent = player.total_tokens_spent.saturating_add(booster_cost);
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SettleOpenBooster<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::Booster @ PonzimonError::NoBoosterPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        seeds = [CARD_CATALOG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub card_catalog: Box<Account<'info, CardCatalog>>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    pub token_mint: Account<'info, Mint>,
+    /// CHECK: The account's data is validated manually within the handler.
+    pub randomness_account_data: AccountInfo<'info>,
+}
+
+pub fn settle_open_booster(ctx: Context<SettleOpenBooster>) -> Result<()> {
+    let clock: Clock = Clock::get()?;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    // Security: Validate minimum delay for randomness
+    validate_randomness_delay(player.commit_slot, clock.slot)?;
+
+    // Verify the randomness account
+    if ctx.accounts.randomness_account_data.key() != player.randomness_account {
+        return Err(PonzimonError::InvalidRandomnessAccount.into());
+    }
+    let randomness_data =
+        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
+    if randomness_data.seed_slot != player.commit_slot {
+        return Err(PonzimonError::RandomnessExpired.into());
+    }
+    let random_value = randomness_data
+        .get_value(&clock)
+        .map_err(|_| PonzimonError::RandomnessNotResolved)?;
+    msg!("random_value ---- {:?}", random_value);
+
+    // Settle rewards before changing berry consumption
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        clock.slot,
+    );
+    player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
+
+    let mut card_ids = [0u16; 5];
+    let mut card_serials = [0u32; 5];
+    for i in 0..5 {
+        // Use a different slice of the random value for each card
+        let slice_start = i * 4;
+        let slice_end = slice_start + 4;
+        let mut random_bytes: [u8; 4] = [0; 4];
+        random_bytes.copy_from_slice(&random_value[slice_start..slice_end]);
+        let random_u32 = u32::from_le_bytes(random_bytes);
+
+        let random_percent = random_u32 % 1000;
+
+        let rarity = match random_percent {
+            0..=499 => COMMON,        // 50.0%
+            500..=749 => UNCOMMON,    // 25.0%
+            750..=899 => RARE,        // 15.0%
+            900..=959 => DOUBLE_RARE, // 6.0%
+            960..=989 => VERY_RARE,   // 3.0%
+            990..=998 => SUPER_RARE,  // 0.9%
+            _ => MEGA_RARE,           // 0.1%
+        };
+
+        // Find a random card of the determined rarity
+        let cards_of_rarity = ctx.accounts.card_catalog.drawable_of_rarity(rarity);
+
+        if !cards_of_rarity.is_empty() {
+            let card_index = (random_u32 as usize) % cards_of_rarity.len();
+            let new_card = cards_of_rarity[card_index].to_card();
+
+            require!(
+                (player.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
+                PonzimonError::MachineCapacityExceeded
+            );
+
+            card_serials[i] = player.add_card(new_card)?;
+            card_ids[i] = new_card.id;
+        }
+    }
+
+    // Reset booster state
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+
+    // Update tracking statistics
+    player.total_booster_packs_opened = player.total_booster_packs_opened.saturating_add(1);
+    gs.total_booster_packs_opened = gs.total_booster_packs_opened.saturating_add(1);
+
+    emit!(BoosterOpened {
+        player: player.key(),
+        card_types: card_ids.map(|id| id as u8), // Convert to u8 for compatibility with event size limits
+        card_serials,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  ROLL REWARD MULTIPLIER (Permissionless two-step)
+/// ────────────────────────────────────────────────────────────────────────────
+#[event]
+pub struct RewardMultiplierRolled {
+    pub caller: Pubkey,
+    pub old_multiplier: u64,
+    pub new_multiplier: u64,
+    pub slot: u64,
+}
+
+#[derive(Accounts)]
+pub struct RollRewardMultiplierCommit<'info> {
+    #[account(mut)]
+    pub caller: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = caller,
+        space = 8  /* discriminator */
+        + 32       /* randomness_account */
+        + 8,       /* commit_slot */
+        seeds = [REWARD_MULTIPLIER_ROLL_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub multiplier_roll: Account<'info, RewardMultiplierRoll>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    /// CHECK: The account's data is validated manually within the handler.
+    pub randomness_account_data: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn roll_reward_multiplier_commit(ctx: Context<RollRewardMultiplierCommit>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let gs = &ctx.accounts.global_state;
+    let roll = &mut ctx.accounts.multiplier_roll;
+
+    const ROLL_TIMEOUT_SLOTS: u64 = 100; // Same window as cancel_pending_action
+
+    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+    require!(
+        gs.reward_multiplier_max > 0 && gs.reward_multiplier_min <= gs.reward_multiplier_max,
+        PonzimonError::InvalidRewardMultiplierBounds
+    );
+    require!(
+        slot >= gs
+            .last_rate_update_slot
+            .saturating_add(gs.rate_update_cooldown_slots),
+        PonzimonError::CooldownNotExpired
+    );
+    // A roll whose randomness never resolved may be replaced after a timeout
+    require!(
+        roll.commit_slot == 0 || slot > roll.commit_slot.saturating_add(ROLL_TIMEOUT_SLOTS),
+        PonzimonError::RollAlreadyPending
+    );
+
+    let switchboard_program_id = &Pubkey::from_str(SWITCHBOARD_PROGRAM_ID).unwrap();
+    require!(
+        ctx.accounts.randomness_account_data.owner == switchboard_program_id,
+        PonzimonError::InvalidRandomnessAccountOwner
+    );
+    let randomness_data =
+        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
+    if randomness_data.seed_slot != slot - 1 {
+        return Err(PonzimonError::RandomnessAlreadyRevealed.into());
+    }
+
+    roll.randomness_account = ctx.accounts.randomness_account_data.key();
+    roll.commit_slot = randomness_data.seed_slot;
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct RollRewardMultiplierSettle<'info> {
+    pub caller: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        mut,
+        constraint = multiplier_roll.commit_slot != 0 @ PonzimonError::NoRollPending,
+        seeds = [REWARD_MULTIPLIER_ROLL_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub multiplier_roll: Account<'info, RewardMultiplierRoll>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    /// CHECK: The account's data is validated manually within the handler.
+    pub randomness_account_data: AccountInfo<'info>,
+}
+
+pub fn roll_reward_multiplier_settle(ctx: Context<RollRewardMultiplierSettle>) -> Result<()> {
+    let clock: Clock = Clock::get()?;
+    let gs = &mut ctx.accounts.global_state;
+    let roll = &mut ctx.accounts.multiplier_roll;
+
+    // Security: Validate minimum delay for randomness
+    validate_randomness_delay(roll.commit_slot, clock.slot)?;
+
+    // Verify the randomness account
+    if ctx.accounts.randomness_account_data.key() != roll.randomness_account {
+        return Err(PonzimonError::InvalidRandomnessAccount.into());
+    }
+    let randomness_data =
+        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
+    if randomness_data.seed_slot != roll.commit_slot {
+        return Err(PonzimonError::RandomnessExpired.into());
+    }
+    let random_value = randomness_data
+        .get_value(&clock)
+        .map_err(|_| PonzimonError::RandomnessNotResolved)?;
+
+    // Emissions up to now are credited at the old multiplier
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        clock.slot,
+    );
+
+    let mut multiplier_bytes: [u8; 4] = [0; 4];
+    multiplier_bytes.copy_from_slice(&random_value[0..4]);
+    let random_u32 = u32::from_le_bytes(multiplier_bytes);
+
+    let old_multiplier = gs.reward_rate_multiplier;
+    let new_multiplier = roll_reward_multiplier(
+        random_u32,
+        gs.reward_multiplier_min,
+        gs.reward_multiplier_max,
+    );
+
+    gs.reward_rate_multiplier = new_multiplier;
+    gs.last_rate_update_slot = clock.slot;
+    record_rate_history(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        clock.slot,
+        false,
+    );
+
+    roll.randomness_account = Pubkey::default();
+    roll.commit_slot = 0;
+
+    msg!(
+        "Reward rate multiplier updated to {}/{}",
+        new_multiplier,
+        REWARD_RATE_MULTIPLIER_SCALE
+    );
+    emit!(RewardMultiplierRolled {
+        caller: ctx.accounts.caller.key(),
+        old_multiplier,
+        new_multiplier,
+        slot: clock.slot,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ToggleProduction<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ PonzimonError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+pub fn toggle_production(ctx: Context<ToggleProduction>, enable: bool) -> Result<()> {
+    let global_state = &mut ctx.accounts.global_state;
+    global_state.production_enabled = enable;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateParameters<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ PonzimonError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, global_state.token_mint.as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, global_state.token_mint.as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+}
+
+/// Updates a single parameter in the global state.
+///
+/// # Arguments
+///
+/// * `ctx` - The context for the instruction.
+/// * `parameter_index` - The index of the parameter to update:
+///     - 0: ReferralFee (u8)
+///     - 1: BurnRate (u8)
+///     - 2: CooldownSlots (u64)
+///     - 3: DustThresholdDivisor (u64)
+///     - 4: InitialFarmPurchaseFeeLamports (u64)
+///     - 5: BoosterPackCostMicrotokens (u64)
+///     - 6: GambleFeeLamports (u64)
+///     - 7: StakingLockupSlots (u64)
+///     - 8: TokenRewardRate (u64)
+///     - 9: RewardRate (u64)
+///     - 10: HalvingInterval (u64); 0 switches to a flat rate. Halvings are
+///       counted from the change, keeping the rate in force at that slot
+///     - 11: RewardMultiplierMin (u32)
+///     - 12: RewardMultiplierMax (u32)
+///     - 13: RateUpdateCooldownSlots (u64)
+///     - 14: VestingPercent (u8)
+///     - 15: VestingDurationSlots (u64)
+///     - 16: MiningReferralBps (u16)
+///     - 17: CardTransferFee (u64)
+///     - 18: MarketplaceFeeBps (u16)
+/// * `parameter_value` - The new value for the parameter.
+pub fn update_parameter(
+    ctx: Context<UpdateParameters>,
+    parameter_index: u8,
+    parameter_value: u64,
+) -> Result<()> {
+    let global_state = &mut ctx.accounts.global_state;
+
+    match parameter_index {
+        0 => {
+            // ReferralFee
+            require!(parameter_value <= 50, PonzimonError::InvalidReferralFee);
+            global_state.referral_fee = parameter_value as u8;
+        }
+        1 => {
+            // BurnRate
+            require!(parameter_value <= 100, PonzimonError::InvalidBurnRate);
+            global_state.burn_rate = parameter_value as u8;
+        }
+        2 => {
+            // CooldownSlots
+            require!(parameter_value > 0, PonzimonError::InvalidCooldownSlots);
+            global_state.cooldown_slots = parameter_value;
+        }
+        3 => {
+            // DustThresholdDivisor
+            require!(
+                parameter_value > 0,
+                PonzimonError::InvalidDustThresholdDivisor
+            );
+            global_state.dust_threshold_divisor = parameter_value;
+        }
+        4 => {
+            // InitialFarmPurchaseFeeLamports
+            global_state.initial_farm_purchase_fee_lamports = parameter_value;
+        }
+        5 => {
+            // BoosterPackCostMicrotokens
+            global_state.booster_pack_cost_microtokens = parameter_value;
+        }
+        6 => {
+            // GambleFeeLamports
+            global_state.gamble_fee_lamports = parameter_value;
+        }
+        7 => {
+            // StakingLockupSlots
+            global_state.staking_lockup_slots = parameter_value;
+        }
+        8 => {
+            // TokenRewardRate
+            global_state.token_reward_rate = parameter_value;
+        }
+        9 => {
+            // RewardRate
+            let slot = Clock::get()?.slot;
+            // Settle emissions at the old rate before it changes
+            update_pool(
+                global_state,
+                &ctx.accounts.emission_schedule,
+                &mut ctx.accounts.rate_history,
+                slot,
+            );
+            global_state.reward_rate = parameter_value;
+            record_rate_history(
+                global_state,
+                &ctx.accounts.emission_schedule,
+                &mut ctx.accounts.rate_history,
+                slot,
+                true,
+            );
+        }
+        10 => {
+            // HalvingInterval
+            let slot = Clock::get()?.slot;
+            // Settle emissions under the old schedule before it changes
+            update_pool(
+                global_state,
+                &ctx.accounts.emission_schedule,
+                &mut ctx.accounts.rate_history,
+                slot,
+            );
+            rebase_halving_schedule(global_state, slot);
+            global_state.halving_interval = parameter_value;
+        }
+        11 => {
+            // RewardMultiplierMin
+            require!(
+                parameter_value <= global_state.reward_multiplier_max as u64,
+                PonzimonError::InvalidRewardMultiplierBounds
+            );
+            global_state.reward_multiplier_min = parameter_value as u32;
+        }
+        12 => {
+            // RewardMultiplierMax
+            require!(
+                parameter_value > 0
+                    && parameter_value <= u32::MAX as u64
+                    && parameter_value >= global_state.reward_multiplier_min as u64,
+                PonzimonError::InvalidRewardMultiplierBounds
+            );
+            global_state.reward_multiplier_max = parameter_value as u32;
+        }
+        13 => {
+            // RateUpdateCooldownSlots
+            require!(parameter_value > 0, PonzimonError::InvalidCooldownSlots);
+            global_state.rate_update_cooldown_slots = parameter_value;
+        }
+        14 => {
+            // VestingPercent
+            require!(parameter_value <= 100, PonzimonError::InvalidVestingPercent);
+            global_state.vesting_percent = parameter_value as u8;
+        }
+        15 => {
+            // VestingDurationSlots
+            require!(parameter_value > 0, PonzimonError::InvalidVestingDuration);
+            global_state.vesting_duration_slots = parameter_value;
+        }
+        16 => {
+            // MiningReferralBps
+            require!(
+                parameter_value <= MAX_MINING_REFERRAL_BPS,
+                PonzimonError::InvalidReferralFee
+            );
+            global_state.mining_referral_bps = parameter_value as u16;
+        }
+        17 => {
+            // CardTransferFee
+            global_state.card_transfer_fee = parameter_value;
+        }
+        18 => {
+            // MarketplaceFeeBps
+            require!(
+                parameter_value <= MAX_MARKETPLACE_FEE_BPS,
+                PonzimonError::InvalidMarketplaceFee
+            );
+            global_state.marketplace_fee_bps = parameter_value as u16;
+        }
+        _ => return err!(PonzimonError::InvalidParameterIndex),
+    }
+
+    Ok(())
+}
+
+/// Sets the curve entry for reaching `level` (1-based). Cards already past a
+/// changed level keep their stats; a zero `xp_required` caps leveling there.
+pub fn set_card_level(
+    ctx: Context<UpdateParameters>,
+    level: u8,
+    xp_required: u32,
+    hashpower_bonus_bps: u16,
+    berry_increase: u8,
+) -> Result<()> {
+    require!(
+        level >= 1 && level as usize <= MAX_CARD_LEVEL,
+        PonzimonError::InvalidCardLevel
+    );
+    let global_state = &mut ctx.accounts.global_state;
+    global_state.card_level_curve[level as usize - 1] = CardLevel {
+        xp_required,
+        hashpower_bonus_bps,
+        berry_increase,
+    };
+    validate_card_level_curve(&global_state.card_level_curve)?;
+
+    emit!(CardLevelCurveUpdated {
+        level,
+        xp_required,
+        hashpower_bonus_bps,
+        berry_increase,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ PonzimonError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, global_state.token_mint.as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, global_state.token_mint.as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+}
+
+pub fn update_pool_manual(ctx: Context<UpdatePool>) -> Result<()> {
+    let global_state = &mut ctx.accounts.global_state;
+    let slot_now: u64 = Clock::get()?.slot;
+
+    update_pool(
+        global_state,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        slot_now,
+    );
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  ADMIN: EMISSION SCHEDULE
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct InitializeEmissionSchedule<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8  /* discriminator */
+        + 32       /* token_mint */
+        + 1        /* segment_count */
+        + MAX_EMISSION_SEGMENTS * (8 + 8 + 9), /* segments: start_slot + rate_per_slot + end_slot (Option<u64>) */
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_emission_schedule(ctx: Context<InitializeEmissionSchedule>) -> Result<()> {
+    let schedule = &mut ctx.accounts.emission_schedule;
+    schedule.token_mint = ctx.accounts.token_mint.key();
+    schedule.segment_count = 0;
+    schedule.segments = [EmissionSegment::default(); MAX_EMISSION_SEGMENTS];
+    Ok(())
+}
+
+#[event]
+pub struct EmissionSegmentAdded {
+    pub segment_index: u8,
+    pub start_slot: u64,
+    pub rate_per_slot: u64,
+    pub end_slot: Option<u64>,
+}
+
+#[derive(Accounts)]
+pub struct AddEmissionSegment<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+}
+
+pub fn add_emission_segment(
+    ctx: Context<AddEmissionSegment>,
+    start_slot: u64,
+    rate_per_slot: u64,
+    end_slot: Option<u64>,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let schedule = &mut ctx.accounts.emission_schedule;
+
+    schedule.add_segment(
+        EmissionSegment {
+            start_slot,
+            rate_per_slot,
+            end_slot,
+        },
+        slot,
+    )?;
+
+    emit!(EmissionSegmentAdded {
+        segment_index: schedule.segment_count - 1,
+        start_slot,
+        rate_per_slot,
+        end_slot,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeRateHistory<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8  /* discriminator */
+        + 32       /* token_mint */
+        + 2 + 2    /* head + count */
+        + RATE_HISTORY_LEN * (8 + 8 + 8 + 16 + 8), /* entries: slot + reward_rate + reward_rate_multiplier + acc_tokens_per_hashpower + total_hashpower */
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn initialize_rate_history(ctx: Context<InitializeRateHistory>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let history = &mut ctx.accounts.rate_history;
+    history.token_mint = ctx.accounts.token_mint.key();
+    history.head = 0;
+    history.count = 0;
+    history.entries = [RateHistoryEntry::default(); RATE_HISTORY_LEN];
+
+    // Seed the buffer with the rate in force today
+    record_rate_history(
+        &ctx.accounts.global_state,
+        &ctx.accounts.emission_schedule,
+        history,
+        slot,
+        true,
+    );
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  ADMIN: IDLE EMISSION POLICY
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SetIdleEmissionPolicy<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    /// Required when switching to the treasury policy; replaces the stored treasury
+    #[account(
+        constraint = emission_treasury.mint == token_mint.key() @ PonzimonError::InvalidTokenMint
+    )]
+    pub emission_treasury: Option<Account<'info, TokenAccount>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Chooses what happens to emissions while no hashpower is staked:
+/// 0 drops them, 1 rolls them over to the next miners, 2 sends them to the treasury.
+pub fn set_idle_emission_policy(ctx: Context<SetIdleEmissionPolicy>, policy: u8) -> Result<()> {
+    require!(
+        policy <= IDLE_EMISSION_TREASURY,
+        PonzimonError::InvalidIdleEmissionPolicy
+    );
+    let gs = &mut ctx.accounts.global_state;
+
+    // Settle slots elapsed so far under the old policy
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        Clock::get()?.slot,
+    );
+
+    if let Some(treasury) = &ctx.accounts.emission_treasury {
+        gs.emission_treasury = treasury.key();
+    }
+    if policy == IDLE_EMISSION_TREASURY {
+        require!(
+            gs.emission_treasury != Pubkey::default(),
+            PonzimonError::EmissionTreasuryNotSet
+        );
+    }
+    gs.idle_emission_policy = policy;
+
+    Ok(())
+}
+
+#[event]
+pub struct TreasuryEmissionSwept {
+    pub amount: u64,
+    pub emission_treasury: Pubkey,
+}
+
+#[derive(Accounts)]
+pub struct SweepTreasuryEmission<'info> {
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = global_state.emission_treasury @ PonzimonError::Unauthorized
+    )]
+    pub emission_treasury: Account<'info, TokenAccount>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub token_program: Program<'info, Token>,
+}
+
+/// Clears the idle emission owed to the treasury. Swept emission leaves the
+/// vault like a mining claim, so it is booked as claimed.
+fn take_treasury_emission(gs: &mut GlobalState) -> Result<u64> {
+    let amount = gs.treasury_emission_owed;
+    require!(amount > 0, PonzimonError::NothingToSweep);
+    gs.treasury_emission_owed = 0;
+    gs.total_rewards_claimed = gs.total_rewards_claimed.saturating_add(amount);
+    Ok(amount)
+}
+
+/// Permissionless: moves idle emission owed to the treasury out of the rewards
+/// vault. The destination is fixed by the admin, so anyone may crank it.
+pub fn sweep_treasury_emission(ctx: Context<SweepTreasuryEmission>) -> Result<()> {
+    let gs = &mut ctx.accounts.global_state;
+    let amount = take_treasury_emission(gs)?;
+
+    let token_mint_key = ctx.accounts.token_mint.key();
+    let seeds = &[
+        GLOBAL_STATE_SEED,
+        token_mint_key.as_ref(),
+        &[ctx.bumps.global_state],
+    ];
+    let signer = &[&seeds[..]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.rewards_vault.to_account_info(),
+                to: ctx.accounts.emission_treasury.to_account_info(),
+                authority: gs.to_account_info(),
+            },
+            signer,
+        ),
+        amount,
+    )?;
+
+    emit!(TreasuryEmissionSwept {
+        amount,
+        emission_treasury: ctx.accounts.emission_treasury.key(),
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  ADMIN: REFERRAL TIERS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct InitializeReferralConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8  /* discriminator */
+        + 32       /* token_mint */
+        + 1        /* level_count */
+        + MAX_REFERRAL_LEVELS * 2, /* level_bps */
+        seeds = [REFERRAL_CONFIG_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub referral_config: Box<Account<'info, ReferralConfig>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Creates the referral config with no tiers, so referrals keep paying a single
+/// level at `GlobalState.referral_fee` until tiers are set.
+pub fn initialize_referral_config(ctx: Context<InitializeReferralConfig>) -> Result<()> {
+    let config = &mut ctx.accounts.referral_config;
+    config.token_mint = ctx.accounts.token_mint.key();
+    config.level_count = 0;
+    config.level_bps = [0; MAX_REFERRAL_LEVELS];
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateReferralTiers<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REFERRAL_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub referral_config: Box<Account<'info, ReferralConfig>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Sets the basis-point share of each referral level, direct referrer first.
+/// An empty list goes back to the single-level `referral_fee`.
+pub fn update_referral_tiers(ctx: Context<UpdateReferralTiers>, level_bps: Vec<u16>) -> Result<()> {
+    require!(
+        level_bps.len() <= MAX_REFERRAL_LEVELS,
+        PonzimonError::InvalidReferralTiers
+    );
+    let total_bps: u64 = level_bps.iter().map(|bps| *bps as u64).sum();
+    require!(
+        total_bps <= MAX_REFERRAL_TIER_TOTAL_BPS,
+        PonzimonError::InvalidReferralTiers
+    );
+
+    let config = &mut ctx.accounts.referral_config;
+    config.level_count = level_bps.len() as u8;
+    config.level_bps = [0; MAX_REFERRAL_LEVELS];
+    config.level_bps[..level_bps.len()].copy_from_slice(&level_bps);
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  ADMIN: CARD CATALOG
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct InitializeCardCatalog<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8  /* discriminator */
+        + 32       /* token_mint */
+        + 4 + MAX_CATALOG_CARDS * CATALOG_CARD_SIZE, /* cards */
+        seeds = [CARD_CATALOG_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub card_catalog: Box<Account<'info, CardCatalog>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Creates the card catalog and seeds it with every card in `CARD_DATA`, all
+/// enabled and booster-eligible, so draws match the old hard-coded table.
+pub fn initialize_card_catalog(ctx: Context<InitializeCardCatalog>) -> Result<()> {
+    let catalog = &mut ctx.accounts.card_catalog;
+    catalog.token_mint = ctx.accounts.token_mint.key();
+    catalog.cards = Vec::with_capacity(CARD_DATA.len());
+    for &(id, rarity, hashpower, berry_consumption) in CARD_DATA.iter() {
+        catalog.add(CatalogCard {
+            id,
+            rarity,
+            hashpower,
+            berry_consumption,
+            enabled: true,
+            booster_eligible: true,
+        })?;
+    }
+    Ok(())
+}
+
+#[event]
+pub struct CatalogCardUpdated {
+    pub id: u16,
+    pub rarity: u8,
+    pub hashpower: u16,
+    pub berry_consumption: u8,
+    pub enabled: bool,
+    pub booster_eligible: bool,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCardCatalog<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [CARD_CATALOG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub card_catalog: Box<Account<'info, CardCatalog>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Adds a new card to the catalog. New cards start enabled.
+pub fn add_catalog_card(
+    ctx: Context<UpdateCardCatalog>,
+    id: u16,
+    rarity: u8,
+    hashpower: u16,
+    berry_consumption: u8,
+    booster_eligible: bool,
+) -> Result<()> {
+    let card = CatalogCard {
+        id,
+        rarity,
+        hashpower,
+        berry_consumption,
+        enabled: true,
+        booster_eligible,
+    };
+    ctx.accounts.card_catalog.add(card)?;
+
+    emit!(CatalogCardUpdated {
+        id,
+        rarity,
+        hashpower,
+        berry_consumption,
+        enabled: card.enabled,
+        booster_eligible,
+    });
+    Ok(())
+}
+
+/// Enables or disables a catalog card and sets whether boosters and recycling
+/// can draw it. Stats are fixed once added because owned cards keep a copy.
+pub fn set_catalog_card_flags(
+    ctx: Context<UpdateCardCatalog>,
+    id: u16,
+    enabled: bool,
+    booster_eligible: bool,
+) -> Result<()> {
+    let card = ctx
+        .accounts
+        .card_catalog
+        .get_mut(id)
+        .ok_or(PonzimonError::CatalogCardNotFound)?;
+    card.enabled = enabled;
+    card.booster_eligible = booster_eligible;
+
+    emit!(CatalogCardUpdated {
+        id,
+        rarity: card.rarity,
+        hashpower: card.hashpower,
+        berry_consumption: card.berry_consumption,
+        enabled,
+        booster_eligible,
+    });
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  MIGRATIONS
+/// ────────────────────────────────────────────────────────────────────────────
+/// Grows a program-owned account to `new_size`, with `payer` covering the rent
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_size: usize,
+) -> Result<()> {
+    let rent_due = Rent::get()?
+        .minimum_balance(new_size)
+        .saturating_sub(account.lamports());
+    if rent_due > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            rent_due,
+        )?;
+    }
+    account.realloc(new_size, true)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MigrateGlobalState<'info> {
     #[account(mut)]
-    pub player_wallet: Signer<'info>,
-    #[account(
-        mut,
-        constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
-        constraint = player.pending_action == PendingRandomAction::Booster @ PonzimonError::NoBoosterPending,
-        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
-        bump
-    )]
-    pub player: Box<Account<'info, Player>>,
+    pub authority: Signer<'info>,
+    /// CHECK: Still in the launch layout, so it cannot be deserialized as
+    /// GlobalState yet; the discriminator and authority are checked in the handler
     #[account(
         mut,
+        owner = crate::ID,
         seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
         bump,
     )]
-    pub global_state: Account<'info, GlobalState>,
+    pub global_state: UncheckedAccount<'info>,
     #[account(
-        mut,
         seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
         bump,
     )]
     pub rewards_vault: Account<'info, TokenAccount>,
     pub token_mint: Account<'info, Mint>,
-    /// CHECK: The account's data is validated manually within the handler.
-    pub randomness_account_data: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
 }
 
-pub fn settle_open_booster(ctx: Context<SettleOpenBooster>) -> Result<()> {
-    let clock: Clock = Clock::get()?;
-    let player = &mut ctx.accounts.player;
-    let gs = &mut ctx.accounts.global_state;
-
-    // Security: Validate minimum delay for randomness
-    validate_randomness_delay(player.commit_slot, clock.slot)?;
-
-    // Verify the randomness account
-    if ctx.accounts.randomness_account_data.key() != player.randomness_account {
-        return Err(PonzimonError::InvalidRandomnessAccount.into());
-    }
-    let randomness_data =
-        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
-    if randomness_data.seed_slot != player.commit_slot {
-        return Err(PonzimonError::RandomnessExpired.into());
-    }
-    let random_value = randomness_data
-        .get_value(&clock)
-        .map_err(|_| PonzimonError::RandomnessNotResolved)?;
-    msg!("random_value ---- {:?}", random_value);
-
-    // Settle rewards before changing berry consumption
-    update_pool(gs, clock.slot);
-    player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
-
-    // --- Check if it's time to update the reward rate multiplier ---
-    if clock.slot
-        >= gs
-            .last_rate_update_slot
-            .saturating_add(REWARD_RATE_UPDATE_COOLDOWN_SLOTS)
+/// Single upgrade path for every GlobalState layout change since launch. Grows
+/// the account to `GlobalState::SIZE` and gives every field added since then the
+/// value `initialize_program` would, including those carved out of the old
+/// padding. Must run right after the upgrade, before any instruction reads it.
+pub fn migrate_global_state(ctx: Context<MigrateGlobalState>) -> Result<()> {
+    let info = ctx.accounts.global_state.to_account_info();
+    require!(
+        info.data_len() == GlobalState::LEGACY_SIZE,
+        PonzimonError::AccountAlreadyMigrated
+    );
     {
-        // Use some bytes from the random value to determine the new multiplier.
-        // Let's use bytes 28-29 for this.
-        let mut multiplier_bytes: [u8; 2] = [0; 2];
-        multiplier_bytes.copy_from_slice(&random_value[28..30]);
-        let random_u16 = u16::from_le_bytes(multiplier_bytes);
-
-        // This creates a range from 500 to 1500, which is 0.5x to 1.5x
-        // The average is 1000 (or 1.0x), keeping your economy balanced.
-        let new_multiplier = 500 + (random_u16 as u64 % 1001);
-
-        gs.reward_rate_multiplier = new_multiplier;
-        gs.last_rate_update_slot = clock.slot;
-        msg!(
-            "Reward rate multiplier updated to {}/{}",
-            new_multiplier,
-            REWARD_RATE_MULTIPLIER_SCALE
+        let data = info.try_borrow_data()?;
+        require!(
+            data[..8] == *GlobalState::DISCRIMINATOR,
+            PonzimonError::InvalidAccountLayout
+        );
+        // authority is the first field in both layouts
+        require!(
+            data[8..40] == ctx.accounts.authority.key().to_bytes(),
+            PonzimonError::Unauthorized
         );
     }
 
-    let mut card_ids = [0u16; 5];
-    for i in 0..5 {
-        // Use a different slice of the random value for each card
-        let slice_start = i * 4;
-        let slice_end = slice_start + 4;
-        let mut random_bytes: [u8; 4] = [0; 4];
-        random_bytes.copy_from_slice(&random_value[slice_start..slice_end]);
-        let random_u32 = u32::from_le_bytes(random_bytes);
-
-        let random_percent = random_u32 % 1000;
-
-        let rarity = match random_percent {
-            0..=499 => COMMON,        // 50.0%
-            500..=749 => UNCOMMON,    // 25.0%
-            750..=899 => RARE,        // 15.0%
-            900..=959 => DOUBLE_RARE, // 6.0%
-            960..=989 => VERY_RARE,   // 3.0%
-            990..=998 => SUPER_RARE,  // 0.9%
-            _ => MEGA_RARE,           // 0.1%
-        };
-
-        // Find a random card of the determined rarity
-        let cards_of_rarity: Vec<&(u16, u8, u16, u8)> = CARD_DATA
-            .iter()
-            .filter(|(_, card_rarity, _, _)| *card_rarity == rarity)
-            .collect();
-
-        if !cards_of_rarity.is_empty() {
-            let card_index = (random_u32 as usize) % cards_of_rarity.len();
-            let (card_id, _, hashpower, berry_consumption) = cards_of_rarity[card_index];
-
-            require!(
-                (player.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
-                PonzimonError::MachineCapacityExceeded
-            );
-
-            let new_card = Card {
-                id: *card_id,
-                rarity,
-                hashpower: *hashpower,
-                berry_consumption: *berry_consumption,
-            };
-            player.add_card(new_card)?;
-            card_ids[i] = *card_id;
-        }
-    }
-
-    // Reset booster state
-    player.pending_action = PendingRandomAction::None;
-    player.commit_slot = 0;
-
-    // Update tracking statistics
-    player.total_booster_packs_opened = player.total_booster_packs_opened.saturating_add(1);
-    gs.total_booster_packs_opened = gs.total_booster_packs_opened.saturating_add(1);
+    grow_account(
+        &info,
+        &ctx.accounts.authority.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        GlobalState::SIZE,
+    )?;
 
-    emit!(BoosterOpened {
-        player: player.key(),
-        card_types: card_ids.map(|id| id as u8), // Convert to u8 for compatibility with event size limits
-    });
+    // Everything after rewards_vault is new; the old padding is overwritten
+    let mut gs = GlobalState::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    gs.halving_interval = 0; // Flat rate, as before the upgrade
+    gs.reward_multiplier_min = DEFAULT_REWARD_MULTIPLIER_MIN;
+    gs.reward_multiplier_max = DEFAULT_REWARD_MULTIPLIER_MAX;
+    gs.rate_update_cooldown_slots = REWARD_RATE_UPDATE_COOLDOWN_SLOTS;
+
+    // Past claims were never counted, so start the books from the vault as it
+    // stands: whatever it holds beyond staked principal is unclaimed mining
+    let unclaimed_mining = ctx
+        .accounts
+        .rewards_vault
+        .amount
+        .saturating_sub(gs.total_staked_tokens);
+    gs.total_rewards_claimed = gs.cumulative_rewards.saturating_sub(unclaimed_mining);
+    gs.cumulative_staking_rewards = 0;
+    gs.total_staking_rewards_claimed = 0;
+    gs.acc_reward_remainder = 0;
+
+    gs.idle_emission_policy = IDLE_EMISSION_DROP;
+    gs.emission_treasury = Pubkey::default();
+    gs.rolled_over_emission = 0;
+    gs.treasury_emission_owed = 0;
+    gs.vesting_percent = 0;
+    gs.vesting_duration_slots = DEFAULT_VESTING_DURATION_SLOTS;
+    gs.mining_referral_bps = 0;
+    gs.card_transfer_fee = 0;
+    gs.marketplace_fee_bps = DEFAULT_MARKETPLACE_FEE_BPS;
+    gs.card_level_curve = CardLevel::default_curve();
+    gs.padding = [0; 3];
+    gs.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
 
     Ok(())
 }
 
 #[derive(Accounts)]
-pub struct ToggleProduction<'info> {
+pub struct MigratePlayer<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
-    #[account(
-        mut,
-        has_one = authority @ PonzimonError::Unauthorized
-    )]
-    pub global_state: Account<'info, GlobalState>,
+    pub payer: Signer<'info>,
+    /// CHECK: Still in the launch layout, so it cannot be deserialized as
+    /// Player yet; the discriminator and PDA are checked in the handler
+    #[account(mut, owner = crate::ID)]
+    pub player: UncheckedAccount<'info>,
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
 }
 
-pub fn toggle_production(ctx: Context<ToggleProduction>, enable: bool) -> Result<()> {
-    let global_state = &mut ctx.accounts.global_state;
-    global_state.production_enabled = enable;
+/// Single upgrade path for every Player layout change since launch: rewrites a
+/// launch account into the current layout, resetting every field added since.
+/// Permissionless, since the conversion is fixed: anyone may pay the extra rent.
+/// Players must be migrated before any other instruction can load them.
+pub fn migrate_player(ctx: Context<MigratePlayer>) -> Result<()> {
+    let info = ctx.accounts.player.to_account_info();
+    require!(
+        info.data_len() == Player::LEGACY_SIZE,
+        PonzimonError::AccountAlreadyMigrated
+    );
+    let legacy = {
+        let data = info.try_borrow_data()?;
+        require!(
+            data[..8] == *Player::DISCRIMINATOR,
+            PonzimonError::InvalidAccountLayout
+        );
+        Box::new(
+            LegacyPlayer::deserialize(&mut &data[8..])
+                .map_err(|_| PonzimonError::InvalidAccountLayout)?,
+        )
+    };
+    let (expected_player, _) = Pubkey::find_program_address(
+        &[
+            PLAYER_SEED,
+            legacy.owner.as_ref(),
+            ctx.accounts.token_mint.key().as_ref(),
+        ],
+        ctx.program_id,
+    );
+    require_keys_eq!(
+        info.key(),
+        expected_player,
+        PonzimonError::InvalidPlayerAccount
+    );
+
+    grow_account(
+        &info,
+        &ctx.accounts.payer.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        Player::SIZE,
+    )?;
+    let player = Box::new(legacy.into_player());
+    let mut data = info.try_borrow_mut_data()?;
+    data.fill(0);
+    player.try_serialize(&mut &mut data[..])?;
+
     Ok(())
 }
 
+/// ────────────────────────────────────────────────────────────────────────────
+///  ADMIN: FARM CONFIG
+/// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct UpdateParameters<'info> {
+pub struct InitializeFarmConfig<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
     #[account(
-        mut,
-        has_one = authority @ PonzimonError::Unauthorized
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8  /* discriminator */
+        + 32       /* token_mint */
+        + 4 + MAX_FARM_LEVELS * FARM_LEVEL_SIZE, /* levels */
+        seeds = [FARM_CONFIG_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub farm_config: Box<Account<'info, FarmConfig>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
 }
 
-/// Updates a single parameter in the global state.
-///
-/// # Arguments
-///
-/// * `ctx` - The context for the instruction.
-/// * `parameter_index` - The index of the parameter to update:
-///     - 0: ReferralFee (u8)
-///     - 1: BurnRate (u8)
-///     - 2: CooldownSlots (u64)
-///     - 3: DustThresholdDivisor (u64)
-///     - 4: InitialFarmPurchaseFeeLamports (u64)
-///     - 5: BoosterPackCostMicrotokens (u64)
-///     - 6: GambleFeeLamports (u64)
-///     - 7: StakingLockupSlots (u64)
-///     - 8: TokenRewardRate (u64)
-///     - 9: RewardRate (u64)
-/// * `parameter_value` - The new value for the parameter.
-pub fn update_parameter(
-    ctx: Context<UpdateParameters>,
-    parameter_index: u8,
-    parameter_value: u64,
-) -> Result<()> {
-    let global_state = &mut ctx.accounts.global_state;
-
-    match parameter_index {
-        0 => {
-            // ReferralFee
-            require!(parameter_value <= 50, PonzimonError::InvalidReferralFee);
-            global_state.referral_fee = parameter_value as u8;
-        }
-        1 => {
-            // BurnRate
-            require!(parameter_value <= 100, PonzimonError::InvalidBurnRate);
-            global_state.burn_rate = parameter_value as u8;
-        }
-        2 => {
-            // CooldownSlots
-            require!(parameter_value > 0, PonzimonError::InvalidCooldownSlots);
-            global_state.cooldown_slots = parameter_value;
-        }
-        3 => {
-            // DustThresholdDivisor
-            require!(
-                parameter_value > 0,
-                PonzimonError::InvalidDustThresholdDivisor
-            );
-            global_state.dust_threshold_divisor = parameter_value;
-        }
-        4 => {
-            // InitialFarmPurchaseFeeLamports
-            global_state.initial_farm_purchase_fee_lamports = parameter_value;
-        }
-        5 => {
-            // BoosterPackCostMicrotokens
-            global_state.booster_pack_cost_microtokens = parameter_value;
-        }
-        6 => {
-            // GambleFeeLamports
-            global_state.gamble_fee_lamports = parameter_value;
-        }
-        7 => {
-            // StakingLockupSlots
-            global_state.staking_lockup_slots = parameter_value;
-        }
-        8 => {
-            // TokenRewardRate
-            global_state.token_reward_rate = parameter_value;
-        }
-        9 => {
-            // RewardRate
-            global_state.reward_rate = parameter_value;
-        }
-        _ => return err!(PonzimonError::InvalidParameterIndex),
-    }
-
+/// Creates the farm config with the levels in `FARM_CONFIGS`, so farms match
+/// the old hard-coded table.
+pub fn initialize_farm_config(ctx: Context<InitializeFarmConfig>) -> Result<()> {
+    let config = &mut ctx.accounts.farm_config;
+    config.token_mint = ctx.accounts.token_mint.key();
+    config.levels = FARM_CONFIGS
+        .iter()
+        .map(|&(total_cards, berry_capacity, cost)| FarmLevel {
+            total_cards,
+            berry_capacity,
+            cost,
+        })
+        .collect();
     Ok(())
 }
 
+#[event]
+pub struct FarmLevelUpdated {
+    pub farm_type: u8,
+    pub total_cards: u8,
+    pub berry_capacity: u64,
+    pub cost: u64,
+}
+
 #[derive(Accounts)]
-pub struct UpdatePool<'info> {
-    #[account(mut)]
+pub struct UpdateFarmConfig<'info> {
     pub authority: Signer<'info>,
     #[account(
-        mut,
-        has_one = authority @ PonzimonError::Unauthorized
+        has_one = authority @ PonzimonError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [FARM_CONFIG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub farm_config: Box<Account<'info, FarmConfig>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
 }
 
-pub fn update_pool_manual(ctx: Context<UpdatePool>) -> Result<()> {
-    let global_state = &mut ctx.accounts.global_state;
-    let slot_now: u64 = Clock::get()?.slot;
-
-    update_pool(global_state, slot_now);
+/// Rebalances an existing farm level, or adds the next one when `farm_type`
+/// equals the current level count. Players at a rebalanced level get the new
+/// limits the next time they stake.
+pub fn set_farm_level(
+    ctx: Context<UpdateFarmConfig>,
+    farm_type: u8,
+    total_cards: u8,
+    berry_capacity: u64,
+    cost: u64,
+) -> Result<()> {
+    ctx.accounts.farm_config.set_level(
+        farm_type,
+        FarmLevel {
+            total_cards,
+            berry_capacity,
+            cost,
+        },
+    )?;
 
+    emit!(FarmLevelUpdated {
+        farm_type,
+        total_cards,
+        berry_capacity,
+        cost,
+    });
     Ok(())
 }
 
@@ -1603,6 +5287,17 @@ pub struct ResetPlayer<'info> {
         constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
     #[account(
         mut,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
@@ -1620,7 +5315,12 @@ pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
     let slot = Clock::get()?.slot;
 
     // Update pool to current slot
-    update_pool(gs, slot);
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        slot,
+    );
 
     // Store the old berry consumption and power to update global state
     let old_berries = player.berries;
@@ -1629,11 +5329,10 @@ pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
     // Reset player's berry consumption, power, and farm
     player.berries = 0;
     player.total_hashpower = 0;
-    let (total_cards, berry_capacity, _) = FARM_CONFIGS[0];
     player.farm = Farm {
         farm_type: 0,
-        total_cards,
-        berry_capacity,
+        total_cards: 0,
+        berry_capacity: 0,
     };
     player.cards = [Card::default(); MAX_CARDS_PER_PLAYER as usize]; // Clear all cards
     player.card_count = 0;
@@ -1646,6 +5345,7 @@ pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
     // Update player's last claim slot and accumulator
     player.last_claim_slot = slot;
     player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
+    player.acc_reward_remainder = 0;
 
     // Reset any pending operations
     player.pending_action = PendingRandomAction::None;
@@ -1681,11 +5381,36 @@ pub struct RecycleCardsCommit<'info> {
         bump,
     )]
     pub rewards_vault: Account<'info, TokenAccount>,
+    /// Vault page holding the cards, for `recycle_vault_cards_commit`
+    #[account(
+        seeds = [CARD_VAULT_SEED, player.key().as_ref(), &[card_vault.page]],
+        bump,
+    )]
+    pub card_vault: Option<Box<Account<'info, CardVault>>>,
     pub token_mint: Account<'info, Mint>,
     /// CHECK: The account's data is validated manually within the handler.
     pub randomness_account_data: AccountInfo<'info>,
 }
 
+/// Checks the committed Switchboard randomness account is fresh and returns its seed slot
+fn verify_recycle_randomness(
+    randomness_account_data: &AccountInfo,
+    expected: Pubkey,
+    slot: u64,
+) -> Result<u64> {
+    // Verify the randomness account
+    if randomness_account_data.key() != expected {
+        return Err(PonzimonError::InvalidRandomnessAccount.into());
+    }
+    // Validate Switchboard randomness account
+    let randomness_data =
+        RandomnessAccountData::parse(randomness_account_data.data.borrow()).unwrap();
+    if randomness_data.seed_slot != slot - 1 {
+        return Err(PonzimonError::RandomnessAlreadyRevealed.into());
+    }
+    Ok(randomness_data.seed_slot)
+}
+
 pub fn recycle_cards_commit(ctx: Context<RecycleCardsCommit>, card_indices: Vec<u8>) -> Result<()> {
     let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
@@ -1716,16 +5441,11 @@ pub fn recycle_cards_commit(ctx: Context<RecycleCardsCommit>, card_indices: Vec<
         require!(!player.is_card_staked(index), PonzimonError::CardIsStaked);
     }
 
-    // Verify the randomness account
-    if ctx.accounts.randomness_account_data.key() != player.randomness_account {
-        return Err(PonzimonError::InvalidRandomnessAccount.into());
-    }
-    // Validate Switchboard randomness account
-    let randomness_data =
-        RandomnessAccountData::parse(ctx.accounts.randomness_account_data.data.borrow()).unwrap();
-    if randomness_data.seed_slot != slot - 1 {
-        return Err(PonzimonError::RandomnessAlreadyRevealed.into());
-    }
+    let seed_slot = verify_recycle_randomness(
+        &ctx.accounts.randomness_account_data,
+        player.randomness_account,
+        slot,
+    )?;
 
     // Create array from vector (pad with 0s if needed)
     let mut card_indices_array = [0u8; 128];
@@ -1738,7 +5458,70 @@ pub fn recycle_cards_commit(ctx: Context<RecycleCardsCommit>, card_indices: Vec<
         card_indices: card_indices_array,
         card_count: card_indices.len() as u8,
     };
-    player.commit_slot = randomness_data.seed_slot;
+    player.commit_slot = seed_slot;
+
+    // Update recycling attempt tracking
+    gs.total_card_recycling_attempts = gs.total_card_recycling_attempts.saturating_add(1);
+
+    Ok(())
+}
+
+/// Same as `recycle_cards_commit`, addressing the cards by serial.
+pub fn recycle_cards_commit_by_serial(
+    ctx: Context<RecycleCardsCommit>,
+    card_serials: Vec<u32>,
+) -> Result<()> {
+    let card_indices = card_serials
+        .iter()
+        .map(|&serial| ctx.accounts.player.find_card_by_serial(serial))
+        .collect::<Result<Vec<u8>>>()?;
+    recycle_cards_commit(ctx, card_indices)
+}
+
+/// Same as `recycle_cards_commit` for cards stored in `card_vault`. Upgraded
+/// cards are put back on the same page at settle.
+pub fn recycle_vault_cards_commit(
+    ctx: Context<RecycleCardsCommit>,
+    card_serials: Vec<u32>,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+    let vault = ctx
+        .accounts
+        .card_vault
+        .as_ref()
+        .ok_or(PonzimonError::CardVaultRequired)?;
+
+    // Guards
+    require!(gs.production_enabled, PonzimonError::ProductionDisabled);
+    require!(
+        !card_serials.is_empty() && card_serials.len() <= MAX_VAULT_RECYCLE_CARDS,
+        PonzimonError::InvalidRecycleCardCount
+    );
+    for (i, serial) in card_serials.iter().enumerate() {
+        require!(
+            !card_serials[..i].contains(serial),
+            PonzimonError::DuplicateCardSerials
+        );
+        require!(vault.contains(*serial), PonzimonError::CardSerialNotFound);
+    }
+
+    let seed_slot = verify_recycle_randomness(
+        &ctx.accounts.randomness_account_data,
+        player.randomness_account,
+        slot,
+    )?;
+
+    let mut card_serials_array = [0u32; MAX_VAULT_RECYCLE_CARDS];
+    card_serials_array[..card_serials.len()].copy_from_slice(&card_serials);
+
+    player.pending_action = PendingRandomAction::RecycleVault {
+        page: vault.page,
+        card_serials: card_serials_array,
+        card_count: card_serials.len() as u8,
+    };
+    player.commit_slot = seed_slot;
 
     // Update recycling attempt tracking
     gs.total_card_recycling_attempts = gs.total_card_recycling_attempts.saturating_add(1);
@@ -1753,7 +5536,10 @@ pub struct RecycleCardsSettle<'info> {
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ PonzimonError::Unauthorized,
-        constraint = matches!(player.pending_action, PendingRandomAction::Recycle { .. }) @ PonzimonError::NoRecyclePending,
+        constraint = matches!(
+            player.pending_action,
+            PendingRandomAction::Recycle { .. } | PendingRandomAction::RecycleVault { .. }
+        ) @ PonzimonError::NoRecyclePending,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -1764,12 +5550,35 @@ pub struct RecycleCardsSettle<'info> {
         bump,
     )]
     pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        mut,
+        seeds = [RATE_HISTORY_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rate_history: Box<Account<'info, RateHistory>>,
+    #[account(
+        seeds = [CARD_CATALOG_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub card_catalog: Box<Account<'info, CardCatalog>>,
     #[account(
         mut,
         seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
         bump,
     )]
     pub rewards_vault: Account<'info, TokenAccount>,
+    /// Vault page holding the cards of a pending vault recycle
+    #[account(
+        mut,
+        seeds = [CARD_VAULT_SEED, player.key().as_ref(), &[card_vault.page]],
+        bump,
+    )]
+    pub card_vault: Option<Box<Account<'info, CardVault>>>,
     pub token_mint: Account<'info, Mint>,
     /// CHECK: The account's data is validated manually within the handler.
     pub randomness_account_data: AccountInfo<'info>,
@@ -1797,61 +5606,91 @@ pub fn recycle_cards_settle(ctx: Context<RecycleCardsSettle>) -> Result<()> {
         .map_err(|_| PonzimonError::RandomnessNotResolved)?;
 
     // Settle rewards before changing player state
-    update_pool(gs, clock.slot);
+    update_pool(
+        gs,
+        &ctx.accounts.emission_schedule,
+        &mut ctx.accounts.rate_history,
+        clock.slot,
+    );
     player.last_acc_tokens_per_hashpower = gs.acc_tokens_per_hashpower;
 
-    // Extract recycled card data from pending action
-    let (card_indices_array, card_count) = if let PendingRandomAction::Recycle {
-        card_indices,
-        card_count,
-    } = player.pending_action
-    {
-        (card_indices, card_count)
-    } else {
-        return Err(PonzimonError::NoRecyclePending.into());
+    // Rarity of each recycled card in commit order, None if it is gone
+    let pending = player.pending_action.clone();
+    let (rarities, card_count): (Vec<Option<u8>>, u8) = match &pending {
+        PendingRandomAction::Recycle {
+            card_indices,
+            card_count,
+        } => (
+            card_indices[..*card_count as usize]
+                .iter()
+                .map(|&index| {
+                    (index < player.card_count).then(|| player.cards[index as usize].rarity)
+                })
+                .collect(),
+            *card_count,
+        ),
+        PendingRandomAction::RecycleVault {
+            page,
+            card_serials,
+            card_count,
+        } => {
+            let vault = ctx
+                .accounts
+                .card_vault
+                .as_ref()
+                .filter(|vault| vault.page == *page)
+                .ok_or(PonzimonError::CardVaultRequired)?;
+            (
+                card_serials[..*card_count as usize]
+                    .iter()
+                    .map(|serial| {
+                        vault
+                            .cards
+                            .iter()
+                            .find(|card| card.serial == *serial)
+                            .map(|card| card.rarity)
+                    })
+                    .collect(),
+                *card_count,
+            )
+        }
+        _ => return Err(PonzimonError::NoRecyclePending.into()),
     };
 
     let mut successful_upgrades = 0u8;
-    let mut new_cards: Vec<(u16, u8, u16, u8)> = Vec::new(); // Store new cards to add
+    let mut new_cards: Vec<Card> = Vec::new(); // Store new cards to add
 
     // Process each card individually with 20% chance for upgrade
-    for i in 0..card_count {
-        let card_index = card_indices_array[i as usize];
-
-        // Validate card index is still valid
-        if (card_index as usize) >= (player.card_count as usize) {
-            continue; // Skip invalid indices
-        }
-
-        let card = &player.cards[card_index as usize];
-        let current_rarity = card.rarity;
+    for (i, rarity) in rarities.iter().enumerate() {
+        // Skip cards that are no longer there
+        let current_rarity = match rarity {
+            Some(rarity) => *rarity,
+            None => continue,
+        };
 
         // Use different slice of random value for each card
-        let random_byte_index = (i as usize) % random_value.len();
+        let random_byte_index = i % random_value.len();
         let random_percent = (random_value[random_byte_index] as u32) % 100;
 
         // 20% chance to upgrade to next rarity
         if random_percent < 20 {
             if let Some(next_rarity) = get_next_rarity(current_rarity) {
                 // Find a random card of the next rarity
-                let cards_of_next_rarity: Vec<&(u16, u8, u16, u8)> = CARD_DATA
-                    .iter()
-                    .filter(|(_, card_rarity, _, _)| *card_rarity == next_rarity)
-                    .collect();
+                let cards_of_next_rarity =
+                    ctx.accounts.card_catalog.drawable_of_rarity(next_rarity);
 
                 if !cards_of_next_rarity.is_empty() {
                     // Use additional randomness for card selection
                     let mut random_bytes: [u8; 4] = [0; 4];
-                    let start_idx = (i as usize * 4) % (random_value.len() - 3);
+                    let start_idx = (i * 4) % (random_value.len() - 3);
                     random_bytes.copy_from_slice(&random_value[start_idx..start_idx + 4]);
                     let random_u32 = u32::from_le_bytes(random_bytes);
 
                     let card_index_in_rarity = (random_u32 as usize) % cards_of_next_rarity.len();
-                    let (card_id, _, hashpower, berry_consumption) =
-                        cards_of_next_rarity[card_index_in_rarity];
+                    let new_card = cards_of_next_rarity[card_index_in_rarity].to_card();
 
                     // Store the new card data to add after removing old cards
-                    new_cards.push((*card_id, next_rarity, *hashpower, *berry_consumption));
+                    new_cards.push(new_card);
                     successful_upgrades += 1;
                 }
             }
@@ -1859,30 +5698,55 @@ pub fn recycle_cards_settle(ctx: Context<RecycleCardsSettle>) -> Result<()> {
         // 80% chance: card is lost (no new card generated)
     }
 
-    // Remove the recycled cards (must sort descending to not mess up indices)
-    let mut card_indices_vec: Vec<u8> = card_indices_array[0..card_count as usize].to_vec();
-    card_indices_vec.sort_by(|a, b| b.cmp(a));
+    let mut recycled_serials = Vec::with_capacity(rarities.len());
+    let mut new_card_serials = Vec::with_capacity(new_cards.len());
+    if let PendingRandomAction::RecycleVault {
+        card_serials,
+        card_count,
+        ..
+    } = &pending
+    {
+        // Vault cards are swapped in place; upgrades never outnumber the cards removed
+        let vault = ctx
+            .accounts
+            .card_vault
+            .as_mut()
+            .ok_or(PonzimonError::CardVaultRequired)?;
+        for &serial in card_serials[..*card_count as usize].iter() {
+            if vault.take(serial).is_ok() {
+                recycled_serials.push(serial);
+            }
+        }
+        for mut new_card in new_cards {
+            player.assign_serial(&mut new_card)?;
+            vault.push(new_card)?;
+            new_card_serials.push(new_card.serial);
+        }
+    } else if let PendingRandomAction::Recycle {
+        card_indices,
+        card_count,
+    } = &pending
+    {
+        // Remove the recycled cards (must sort descending to not mess up indices)
+        let mut card_indices_vec: Vec<u8> = card_indices[0..*card_count as usize].to_vec();
+        card_indices_vec.sort_by(|a, b| b.cmp(a));
 
-    for &index in &card_indices_vec {
-        if (index as usize) < (player.card_count as usize) {
-            player.remove_card(index)?;
+        for &index in &card_indices_vec {
+            if (index as usize) < (player.card_count as usize) {
+                recycled_serials.push(player.cards[index as usize].serial);
+                player.remove_card(index)?;
+            }
         }
-    }
 
-    // Add the new upgraded cards
-    for (card_id, rarity, hashpower, berry_consumption) in new_cards {
-        require!(
-            (player.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
-            PonzimonError::MachineCapacityExceeded
-        );
+        // Add the new upgraded cards
+        for new_card in new_cards {
+            require!(
+                (player.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
+                PonzimonError::MachineCapacityExceeded
+            );
 
-        let new_card = Card {
-            id: card_id,
-            rarity,
-            hashpower,
-            berry_consumption,
-        };
-        player.add_card(new_card)?;
+            new_card_serials.push(player.add_card(new_card)?);
+        }
     }
 
     // Reset recycle state
@@ -1907,6 +5771,8 @@ pub fn recycle_cards_settle(ctx: Context<RecycleCardsSettle>) -> Result<()> {
         player: player.key(),
         successful_upgrades,
         total_recycled: card_count,
+        recycled_serials,
+        new_card_serials,
     });
 
     Ok(())
@@ -1968,6 +5834,178 @@ pub fn update_sol_rewards(ctx: Context<UpdateSolRewards>) -> Result<()> {
     Ok(())
 }
 
+/// ────────────────────────────────────────────────────────────────────────────
+///  AUDIT SUPPLY (read-only)
+/// ────────────────────────────────────────────────────────────────────────────
+#[event]
+pub struct SupplyAudit {
+    pub slot: u64,
+    pub mint_supply: u64,
+    pub vault_balance: u64,
+    pub total_supply: u64,
+    pub burned_tokens: u64,
+    pub cumulative_rewards: u64,
+    pub total_rewards_claimed: u64,
+    pub unclaimed_mining_rewards: u64, // Emitted to the accumulator but not yet paid
+    pub total_staked_tokens: u64,
+    pub staking_rewards_owed: u64,
+    pub required_vault_balance: u64, // Unclaimed mining + staked principal + staking rewards owed
+    pub remaining_emission: u64,     // Supply update_pool may still emit (not yet owed)
+    pub balanced: bool,
+}
+
+#[derive(Accounts)]
+pub struct AuditSupply<'info> {
+    #[account(
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: Account<'info, TokenAccount>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Checks the rewards vault against the program's own books. Emissions are
+/// accrued up to the current slot on a copy of the global state, so nothing is
+/// written. The event is emitted either way so monitoring can read the figures
+/// from the logs of a failed simulation.
+pub fn audit_supply(ctx: Context<AuditSupply>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let mut gs: GlobalState = (*ctx.accounts.global_state).clone();
+    accrue_pool(&mut gs, &ctx.accounts.emission_schedule, slot);
+
+    let unclaimed_mining_rewards = gs
+        .cumulative_rewards
+        .saturating_sub(gs.total_rewards_claimed);
+    let staking_rewards_owed = gs
+        .cumulative_staking_rewards
+        .saturating_sub(gs.total_staking_rewards_claimed);
+    let required_vault_balance = unclaimed_mining_rewards
+        .saturating_add(gs.total_staked_tokens)
+        .saturating_add(staking_rewards_owed);
+
+    let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
+    let remaining_emission = gs.total_supply.saturating_sub(minted_minus_burn);
+
+    let vault_balance = ctx.accounts.rewards_vault.amount;
+    let balanced = vault_balance >= required_vault_balance;
+
+    emit!(SupplyAudit {
+        slot,
+        mint_supply: ctx.accounts.token_mint.supply,
+        vault_balance,
+        total_supply: gs.total_supply,
+        burned_tokens: gs.burned_tokens,
+        cumulative_rewards: gs.cumulative_rewards,
+        total_rewards_claimed: gs.total_rewards_claimed,
+        unclaimed_mining_rewards,
+        total_staked_tokens: gs.total_staked_tokens,
+        staking_rewards_owed,
+        required_vault_balance,
+        remaining_emission,
+        balanced,
+    });
+
+    require!(balanced, PonzimonError::SupplyAuditMismatch);
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+///  PREVIEW PLAYER (read-only)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PlayerPreview {
+    pub slot: u64,
+    pub pending_mining_rewards: u64, // What claim_rewards would send to the wallet right now
+    pub vested_mining_rewards: u64,  // What it would lock in the vesting account
+    pub referral_share: u64,         // Owed to the referrer, including shares held back earlier
+    pub pending_staking_sol: u64,    // Lamports claim_staking_rewards would pay
+    pub pending_staking_tokens: u64, // Tokens claim_staking_rewards would pay
+    pub effective_reward_rate: u64,  // Pool-wide tokens per slot, multiplier applied
+    pub player_reward_rate: u64,     // Player's share of effective_reward_rate
+    pub upgrade_cooldown_slots_remaining: u64, // 0 when upgrade_farm is allowed
+}
+
+#[derive(Accounts)]
+pub struct PreviewPlayer<'info> {
+    #[account(
+        seeds = [PLAYER_SEED, player.owner.as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        seeds = [EMISSION_SCHEDULE_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    #[account(
+        constraint = token_mint.key() == global_state.token_mint @ PonzimonError::InvalidTokenMint
+    )]
+    pub token_mint: Account<'info, Mint>,
+}
+
+/// Runs the same accumulator math as the claim paths on a copy of the global
+/// state, up to the current slot. Nothing is written; the result is returned
+/// through `set_return_data` for use with `simulateTransaction`.
+pub fn preview_player(ctx: Context<PreviewPlayer>) -> Result<PlayerPreview> {
+    let slot = Clock::get()?.slot;
+    let player = &ctx.accounts.player;
+    let schedule = &ctx.accounts.emission_schedule;
+
+    let mut gs: GlobalState = (*ctx.accounts.global_state).clone();
+    accrue_pool(&mut gs, schedule, slot);
+    update_staking_pool(&mut gs, slot);
+
+    // Same split as settle_and_mint_rewards
+    let (pending, _) = pending_mining_rewards(player, &gs);
+    let referral_share = pending_referral_share(player, &gs, pending);
+    let vested_mining_rewards = (pending as u128 * gs.vesting_percent as u128 / 100) as u64;
+    let (pending_staking_sol, pending_tokens) = pending_staking_rewards(player, &gs);
+
+    let effective_reward_rate = ((base_reward_rate_at(&gs, schedule, slot) as u128)
+        .saturating_mul(gs.reward_rate_multiplier as u128)
+        / REWARD_RATE_MULTIPLIER_SCALE as u128) as u64;
+    let player_reward_rate = if gs.total_hashpower > 0 {
+        ((effective_reward_rate as u128).saturating_mul(player.total_hashpower as u128)
+            / gs.total_hashpower as u128) as u64
+    } else {
+        0
+    };
+
+    Ok(PlayerPreview {
+        slot,
+        pending_mining_rewards: pending - vested_mining_rewards,
+        vested_mining_rewards,
+        referral_share: player.referral_rewards_owed.saturating_add(referral_share),
+        pending_staking_sol,
+        pending_staking_tokens: player.claimed_token_rewards.saturating_add(pending_tokens),
+        effective_reward_rate,
+        player_reward_rate,
+        upgrade_cooldown_slots_remaining: player
+            .last_upgrade_slot
+            .saturating_add(gs.cooldown_slots)
+            .saturating_sub(slot),
+    })
+}
+
 /// ────────────────────────────────────────────────────────────────────────────
 ///  CANCEL PENDING ACTION
 /// ────────────────────────────────────────────────────────────────────────────
@@ -1995,6 +6033,13 @@ pub struct CancelPendingAction<'info> {
         bump,
     )]
     pub rewards_vault: Account<'info, TokenAccount>,
+    /// Required to cancel a pending vault recycle
+    #[account(
+        mut,
+        seeds = [CARD_VAULT_SEED, player.key().as_ref(), &[card_vault.page]],
+        bump,
+    )]
+    pub card_vault: Option<Box<Account<'info, CardVault>>>,
     pub token_mint: Account<'info, Mint>,
 }
 
@@ -2031,6 +6076,23 @@ pub fn cancel_pending_action(ctx: Context<CancelPendingAction>) -> Result<()> {
             }
         }
     }
+    if let PendingRandomAction::RecycleVault {
+        page,
+        card_serials,
+        card_count,
+    } = player.pending_action.clone()
+    {
+        let vault = ctx
+            .accounts
+            .card_vault
+            .as_mut()
+            .filter(|vault| vault.page == page)
+            .ok_or(PonzimonError::CardVaultRequired)?;
+        for &serial in card_serials[..card_count as usize].iter() {
+            // Cards already gone are skipped
+            let _ = vault.take(serial);
+        }
+    }
 
     // Reset the player's pending action state, allowing them to try another action.
     player.pending_action = PendingRandomAction::None;
@@ -2096,21 +6158,7 @@ pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64) -> Result<()> {
     update_staking_pool(gs, now);
 
     // Calculate and accumulate pending SOL rewards
-    let pending_sol = ((player.staked_tokens as u128)
-        .checked_mul(
-            gs.acc_sol_rewards_per_token
-                .saturating_sub(player.last_acc_sol_rewards_per_token),
-        )
-        .unwrap_or(0)
-        / ACC_SCALE) as u64;
-
-    let pending_tokens = ((player.staked_tokens as u128)
-        .checked_mul(
-            gs.acc_token_rewards_per_token
-                .saturating_sub(player.last_acc_token_rewards_per_token),
-        )
-        .unwrap_or(0)
-        / ACC_SCALE) as u64;
+    let (pending_sol, pending_tokens) = pending_staking_rewards(player, gs);
 
     // Transfer pending SOL rewards to user before staking more
     if pending_sol > 0 {
@@ -2226,21 +6274,7 @@ pub fn unstake_tokens(ctx: Context<UnstakeTokens>, amount: u64) -> Result<()> {
     update_staking_pool(gs, now);
 
     // Calculate pending SOL rewards (but don't accumulate them)
-    let pending_sol = ((player.staked_tokens as u128)
-        .checked_mul(
-            gs.acc_sol_rewards_per_token
-                .saturating_sub(player.last_acc_sol_rewards_per_token),
-        )
-        .unwrap_or(0)
-        / ACC_SCALE) as u64;
-
-    let pending_tokens = ((player.staked_tokens as u128)
-        .checked_mul(
-            gs.acc_token_rewards_per_token
-                .saturating_sub(player.last_acc_token_rewards_per_token),
-        )
-        .unwrap_or(0)
-        / ACC_SCALE) as u64;
+    let (pending_sol, pending_tokens) = pending_staking_rewards(player, gs);
 
     player.claimed_token_rewards = player.claimed_token_rewards.saturating_add(pending_tokens);
 
@@ -2353,23 +6387,8 @@ pub fn claim_staking_rewards(ctx: Context<ClaimStakingRewards>) -> Result<()> {
 
     update_staking_pool(gs, now);
 
-    // Calculate SOL rewards using accumulator system (similar to token rewards)
-    let pending_sol = ((player.staked_tokens as u128)
-        .checked_mul(
-            gs.acc_sol_rewards_per_token
-                .saturating_sub(player.last_acc_sol_rewards_per_token),
-        )
-        .unwrap_or(0)
-        / ACC_SCALE) as u64;
-
-    // Calculate token rewards (emissions-based)
-    let pending_tokens = ((player.staked_tokens as u128)
-        .checked_mul(
-            gs.acc_token_rewards_per_token
-                .saturating_sub(player.last_acc_token_rewards_per_token),
-        )
-        .unwrap_or(0)
-        / ACC_SCALE) as u64;
+    // Calculate SOL and token (emissions-based) rewards using the accumulators
+    let (pending_sol, pending_tokens) = pending_staking_rewards(player, gs);
 
     let tokens_to_claim = player.claimed_token_rewards.saturating_add(pending_tokens);
 
@@ -2422,6 +6441,9 @@ pub fn claim_staking_rewards(ctx: Context<ClaimStakingRewards>) -> Result<()> {
             tokens_to_claim,
         )?;
         player.claimed_token_rewards = 0;
+        gs.total_staking_rewards_claimed = gs
+            .total_staking_rewards_claimed
+            .saturating_add(tokens_to_claim);
     }
 
     // Update player's accumulator checkpoints
@@ -2658,3 +6680,147 @@ pub fn gamble_settle(ctx: Context<GambleSettle>) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod idle_emission_tests {
+    use super::*;
+
+    /// 1,000 tokens per slot from slot 100, flat rate, no emission schedule
+    fn new_global_state(policy: u8) -> GlobalState {
+        let mut gs = GlobalState::try_from_slice(&vec![0u8; GlobalState::SIZE - 8]).unwrap();
+        gs.last_reward_slot = 100;
+        gs.reward_rate = 1_000;
+        gs.reward_rate_multiplier = REWARD_RATE_MULTIPLIER_SCALE;
+        gs.total_supply = 1_000_000_000;
+        gs.idle_emission_policy = policy;
+        gs
+    }
+
+    fn empty_schedule() -> EmissionSchedule {
+        EmissionSchedule {
+            token_mint: Pubkey::default(),
+            segment_count: 0,
+            segments: [EmissionSegment::default(); MAX_EMISSION_SEGMENTS],
+        }
+    }
+
+    /// Tokens a holder of `hashpower` can claim against the accumulator
+    fn claimable(gs: &GlobalState, hashpower: u64) -> u128 {
+        calculate_pending_rewards(hashpower, gs.acc_tokens_per_hashpower, 0).0
+    }
+
+    #[test]
+    fn test_idle_emission_dropped() {
+        let mut gs = new_global_state(IDLE_EMISSION_DROP);
+        accrue_pool(&mut gs, &empty_schedule(), 200);
+        assert_eq!(gs.last_reward_slot, 200);
+        assert_eq!(gs.cumulative_rewards, 0);
+        assert_eq!(gs.rolled_over_emission, 0);
+        assert_eq!(gs.treasury_emission_owed, 0);
+    }
+
+    #[test]
+    fn test_idle_emission_rolls_over_to_next_miners() {
+        let schedule = empty_schedule();
+        let mut gs = new_global_state(IDLE_EMISSION_ROLLOVER);
+        accrue_pool(&mut gs, &schedule, 200);
+        assert_eq!(gs.rolled_over_emission, 100_000);
+        assert_eq!(gs.acc_tokens_per_hashpower, 0);
+        assert_eq!(gs.cumulative_rewards, 100_000);
+
+        // The first period with hashpower pays its own emission plus the idle one
+        gs.total_hashpower = 10;
+        accrue_pool(&mut gs, &schedule, 300);
+        assert_eq!(gs.rolled_over_emission, 0);
+        assert_eq!(claimable(&gs, 10), 200_000);
+        assert_eq!(gs.cumulative_rewards, 200_000);
+    }
+
+    #[test]
+    fn test_idle_emission_owed_to_treasury() {
+        let schedule = empty_schedule();
+        let mut gs = new_global_state(IDLE_EMISSION_TREASURY);
+        accrue_pool(&mut gs, &schedule, 200);
+        assert_eq!(gs.treasury_emission_owed, 100_000);
+        assert_eq!(gs.rolled_over_emission, 0);
+        assert_eq!(gs.cumulative_rewards, 100_000);
+
+        // Miners arriving later only earn their own period
+        gs.total_hashpower = 10;
+        accrue_pool(&mut gs, &schedule, 300);
+        assert_eq!(claimable(&gs, 10), 100_000);
+        assert_eq!(gs.treasury_emission_owed, 100_000);
+    }
+
+    #[test]
+    fn test_sweep_books_treasury_emission_as_claimed() {
+        let mut gs = new_global_state(IDLE_EMISSION_TREASURY);
+        accrue_pool(&mut gs, &empty_schedule(), 200);
+
+        assert_eq!(take_treasury_emission(&mut gs).unwrap(), 100_000);
+        assert_eq!(gs.treasury_emission_owed, 0);
+        // Nothing swept stays owed by the vault
+        assert_eq!(gs.cumulative_rewards - gs.total_rewards_claimed, 0);
+        assert!(take_treasury_emission(&mut gs).is_err());
+    }
+
+    #[test]
+    fn test_dust_shutdown_pays_out_rolled_over_emission() {
+        let schedule = empty_schedule();
+        let mut gs = new_global_state(IDLE_EMISSION_ROLLOVER);
+        accrue_pool(&mut gs, &schedule, 200);
+
+        // Remaining supply falls to the dust threshold before anyone mines again
+        gs.total_hashpower = 10;
+        gs.dust_threshold_divisor = 100;
+        gs.cumulative_rewards = gs.total_supply - gs.total_supply / 100;
+        let cumulative = gs.cumulative_rewards;
+        accrue_pool(&mut gs, &schedule, 300);
+
+        assert_eq!(gs.reward_rate, 0);
+        assert_eq!(gs.rolled_over_emission, 0);
+        assert_eq!(claimable(&gs, 10), 100_000);
+        assert_eq!(gs.cumulative_rewards, cumulative);
+    }
+}
+
+#[cfg(test)]
+mod halving_interval_tests {
+    use super::*;
+
+    #[test]
+    fn test_rebase_keeps_rate_in_force() {
+        let mut gs = GlobalState::try_from_slice(&vec![0u8; GlobalState::SIZE - 8]).unwrap();
+        gs.start_slot = 1_000;
+        gs.reward_rate = 1_024;
+        gs.halving_interval = 100;
+
+        // Two halvings in: shortening the interval must not apply more at once
+        rebase_halving_schedule(&mut gs, 1_250);
+        gs.halving_interval = 10;
+        assert_eq!(gs.start_slot, 1_250);
+        assert_eq!(
+            calculate_halving_emission(1_250, 1_260, gs.start_slot, 10, gs.reward_rate, 1_000),
+            2_560
+        );
+
+        // Switching back to a flat rate keeps the rate reached so far
+        rebase_halving_schedule(&mut gs, 1_275);
+        gs.halving_interval = 0;
+        assert_eq!(gs.reward_rate, 64);
+        assert_eq!(
+            calculate_halving_emission(1_275, 2_275, gs.start_slot, 0, gs.reward_rate, 1_000),
+            64_000
+        );
+    }
+
+    #[test]
+    fn test_rebase_before_start_is_noop() {
+        let mut gs = GlobalState::try_from_slice(&vec![0u8; GlobalState::SIZE - 8]).unwrap();
+        gs.start_slot = 1_000;
+        gs.reward_rate = 1_024;
+        gs.halving_interval = 100;
+        rebase_halving_schedule(&mut gs, 500);
+        assert_eq!((gs.start_slot, gs.reward_rate), (1_000, 1_024));
+    }
+}
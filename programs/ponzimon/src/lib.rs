@@ -8,6 +8,7 @@ pub mod state;
 
 use errors::PonzimonError;
 use instructions::*;
+use state::SwapWant;
 use std::str::FromStr;
 
 const ADMIN: &str = "8kvqgxQG77pv6RvEou8f2kHSWi3rtx8F7MksXUqNLGmn";
@@ -24,6 +25,7 @@ pub mod ponzimon {
         start_slot: u64,
         total_supply: u64,
         initial_reward_rate: u64,
+        halving_interval: u64,
         cooldown_slots: Option<u64>,
         initial_farm_purchase_fee_lamports: Option<u64>,
         booster_pack_cost_microtokens: Option<u64>,
@@ -36,6 +38,7 @@ pub mod ponzimon {
             start_slot,
             total_supply,
             initial_reward_rate,
+            halving_interval,
             cooldown_slots,
             initial_farm_purchase_fee_lamports,
             booster_pack_cost_microtokens,
@@ -59,6 +62,81 @@ pub mod ponzimon {
     pub fn update_sol_rewards(ctx: Context<UpdateSolRewards>) -> Result<()> {
         instructions::update_sol_rewards(ctx)
     }
+    pub fn initialize_emission_schedule(ctx: Context<InitializeEmissionSchedule>) -> Result<()> {
+        instructions::initialize_emission_schedule(ctx)
+    }
+    pub fn add_emission_segment(
+        ctx: Context<AddEmissionSegment>,
+        start_slot: u64,
+        rate_per_slot: u64,
+        end_slot: Option<u64>,
+    ) -> Result<()> {
+        instructions::add_emission_segment(ctx, start_slot, rate_per_slot, end_slot)
+    }
+    pub fn initialize_rate_history(ctx: Context<InitializeRateHistory>) -> Result<()> {
+        instructions::initialize_rate_history(ctx)
+    }
+    pub fn set_idle_emission_policy(ctx: Context<SetIdleEmissionPolicy>, policy: u8) -> Result<()> {
+        instructions::set_idle_emission_policy(ctx, policy)
+    }
+    pub fn sweep_treasury_emission(ctx: Context<SweepTreasuryEmission>) -> Result<()> {
+        instructions::sweep_treasury_emission(ctx)
+    }
+    pub fn initialize_referral_config(ctx: Context<InitializeReferralConfig>) -> Result<()> {
+        instructions::initialize_referral_config(ctx)
+    }
+    pub fn update_referral_tiers(
+        ctx: Context<UpdateReferralTiers>,
+        level_bps: Vec<u16>,
+    ) -> Result<()> {
+        instructions::update_referral_tiers(ctx, level_bps)
+    }
+    pub fn initialize_card_catalog(ctx: Context<InitializeCardCatalog>) -> Result<()> {
+        instructions::initialize_card_catalog(ctx)
+    }
+    pub fn add_catalog_card(
+        ctx: Context<UpdateCardCatalog>,
+        id: u16,
+        rarity: u8,
+        hashpower: u16,
+        berry_consumption: u8,
+        booster_eligible: bool,
+    ) -> Result<()> {
+        instructions::add_catalog_card(
+            ctx,
+            id,
+            rarity,
+            hashpower,
+            berry_consumption,
+            booster_eligible,
+        )
+    }
+    pub fn set_catalog_card_flags(
+        ctx: Context<UpdateCardCatalog>,
+        id: u16,
+        enabled: bool,
+        booster_eligible: bool,
+    ) -> Result<()> {
+        instructions::set_catalog_card_flags(ctx, id, enabled, booster_eligible)
+    }
+    pub fn migrate_global_state(ctx: Context<MigrateGlobalState>) -> Result<()> {
+        instructions::migrate_global_state(ctx)
+    }
+    pub fn migrate_player(ctx: Context<MigratePlayer>) -> Result<()> {
+        instructions::migrate_player(ctx)
+    }
+    pub fn initialize_farm_config(ctx: Context<InitializeFarmConfig>) -> Result<()> {
+        instructions::initialize_farm_config(ctx)
+    }
+    pub fn set_farm_level(
+        ctx: Context<UpdateFarmConfig>,
+        farm_type: u8,
+        total_cards: u8,
+        berry_capacity: u64,
+        cost: u64,
+    ) -> Result<()> {
+        instructions::set_farm_level(ctx, farm_type, total_cards, berry_capacity, cost)
+    }
     pub fn update_parameter(
         ctx: Context<UpdateParameters>,
         parameter_index: u8,
@@ -66,11 +144,22 @@ pub mod ponzimon {
     ) -> Result<()> {
         instructions::update_parameter(ctx, parameter_index, parameter_value)
     }
+    pub fn set_card_level(
+        ctx: Context<UpdateParameters>,
+        level: u8,
+        xp_required: u32,
+        hashpower_bonus_bps: u16,
+        berry_increase: u8,
+    ) -> Result<()> {
+        instructions::set_card_level(ctx, level, xp_required, hashpower_bonus_bps, berry_increase)
+    }
 
     // ────────────────────────────────────────────────────────────────────────────
     ///  NON ADMIN FUNCTIONS
     // ────────────────────────────────────────────────────────────────────────────
-    pub fn purchase_initial_farm(ctx: Context<PurchaseInitialFarm>) -> Result<()> {
+    pub fn purchase_initial_farm<'info>(
+        ctx: Context<'_, '_, '_, 'info, PurchaseInitialFarm<'info>>,
+    ) -> Result<()> {
         instructions::purchase_initial_farm(ctx)
     }
 
@@ -86,7 +175,97 @@ pub mod ponzimon {
         instructions::discard_card(ctx, card_index)
     }
 
-    pub fn request_open_booster(ctx: Context<RequestOpenBooster>) -> Result<()> {
+    pub fn stake_cards(ctx: Context<StakeCard>, card_indices: Vec<u8>) -> Result<()> {
+        instructions::stake_cards(ctx, card_indices)
+    }
+
+    pub fn unstake_cards(ctx: Context<UnstakeCard>, card_indices: Vec<u8>) -> Result<()> {
+        instructions::unstake_cards(ctx, card_indices)
+    }
+
+    pub fn rearrange_staking(ctx: Context<StakeCard>) -> Result<()> {
+        instructions::rearrange_staking(ctx)
+    }
+
+    pub fn stake_card_by_serial(ctx: Context<StakeCard>, card_serial: u32) -> Result<()> {
+        instructions::stake_card_by_serial(ctx, card_serial)
+    }
+
+    pub fn unstake_card_by_serial(ctx: Context<UnstakeCard>, card_serial: u32) -> Result<()> {
+        instructions::unstake_card_by_serial(ctx, card_serial)
+    }
+
+    pub fn discard_card_by_serial(ctx: Context<DiscardCard>, card_serial: u32) -> Result<()> {
+        instructions::discard_card_by_serial(ctx, card_serial)
+    }
+
+    pub fn set_accept_card_transfers(
+        ctx: Context<SetAcceptCardTransfers>,
+        accept: bool,
+    ) -> Result<()> {
+        instructions::set_accept_card_transfers(ctx, accept)
+    }
+
+    pub fn transfer_card(ctx: Context<TransferCard>, card_serial: u32) -> Result<()> {
+        instructions::transfer_card(ctx, card_serial)
+    }
+
+    pub fn list_card(ctx: Context<ListCard>, card_serial: u32, price: u64) -> Result<()> {
+        instructions::list_card(ctx, card_serial, price)
+    }
+
+    pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+        instructions::cancel_listing(ctx)
+    }
+
+    pub fn buy_listing(ctx: Context<BuyListing>) -> Result<()> {
+        instructions::buy_listing(ctx)
+    }
+
+    pub fn create_swap_offer(
+        ctx: Context<CreateSwapOffer>,
+        offer_id: u64,
+        card_serials: Vec<u32>,
+        wanted: Vec<SwapWant>,
+        wanted_tokens: u64,
+    ) -> Result<()> {
+        instructions::create_swap_offer(ctx, offer_id, card_serials, wanted, wanted_tokens)
+    }
+
+    pub fn cancel_swap_offer(ctx: Context<CancelSwapOffer>) -> Result<()> {
+        instructions::cancel_swap_offer(ctx)
+    }
+
+    pub fn accept_swap_offer(ctx: Context<AcceptSwapOffer>, card_serials: Vec<u32>) -> Result<()> {
+        instructions::accept_swap_offer(ctx, card_serials)
+    }
+
+    pub fn export_card(ctx: Context<ExportCard>, card_serial: u32) -> Result<()> {
+        instructions::export_card(ctx, card_serial)
+    }
+
+    pub fn import_card(ctx: Context<ImportCard>) -> Result<()> {
+        instructions::import_card(ctx)
+    }
+
+    pub fn deposit_to_vault(
+        ctx: Context<DepositToVault>,
+        page: u8,
+        card_serials: Vec<u32>,
+    ) -> Result<()> {
+        instructions::deposit_to_vault(ctx, page, card_serials)
+    }
+
+    pub fn withdraw_from_vault(
+        ctx: Context<WithdrawFromVault>,
+        card_serials: Vec<u32>,
+    ) -> Result<()> {
+        instructions::withdraw_from_vault(ctx, card_serials)
+    }
+
+    pub fn request_open_booster<'info>(
+        ctx: Context<'_, '_, '_, 'info, RequestOpenBooster<'info>>,
+    ) -> Result<()> {
         instructions::request_open_booster(ctx)
     }
     pub fn settle_open_booster(ctx: Context<SettleOpenBooster>) -> Result<()> {
@@ -100,6 +279,29 @@ pub mod ponzimon {
     pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
         instructions::claim_rewards(ctx)
     }
+    pub fn register_referral_code(ctx: Context<RegisterReferralCode>, code: String) -> Result<()> {
+        instructions::register_referral_code(ctx, code)
+    }
+    pub fn release_referral_code(ctx: Context<ReleaseReferralCode>) -> Result<()> {
+        instructions::release_referral_code(ctx)
+    }
+    pub fn set_claim_delegation(
+        ctx: Context<SetClaimDelegation>,
+        keeper: Option<Pubkey>,
+    ) -> Result<()> {
+        instructions::set_claim_delegation(ctx, keeper)
+    }
+    pub fn initialize_vesting_account(ctx: Context<InitializeVestingAccount>) -> Result<()> {
+        instructions::initialize_vesting_account(ctx)
+    }
+    pub fn withdraw_vested(ctx: Context<WithdrawVested>) -> Result<()> {
+        instructions::withdraw_vested(ctx)
+    }
+    pub fn keeper_claim_rewards<'info>(
+        ctx: Context<'_, '_, 'info, 'info, KeeperClaimRewards<'info>>,
+    ) -> Result<()> {
+        instructions::keeper_claim_rewards(ctx)
+    }
 
     pub fn recycle_cards_commit(
         ctx: Context<RecycleCardsCommit>,
@@ -108,6 +310,20 @@ pub mod ponzimon {
         instructions::recycle_cards_commit(ctx, card_indices)
     }
 
+    pub fn recycle_cards_commit_by_serial(
+        ctx: Context<RecycleCardsCommit>,
+        card_serials: Vec<u32>,
+    ) -> Result<()> {
+        instructions::recycle_cards_commit_by_serial(ctx, card_serials)
+    }
+
+    pub fn recycle_vault_cards_commit(
+        ctx: Context<RecycleCardsCommit>,
+        card_serials: Vec<u32>,
+    ) -> Result<()> {
+        instructions::recycle_vault_cards_commit(ctx, card_serials)
+    }
+
     pub fn recycle_cards_settle(ctx: Context<RecycleCardsSettle>) -> Result<()> {
         instructions::recycle_cards_settle(ctx)
     }
@@ -132,6 +348,21 @@ pub mod ponzimon {
     //     instructions::gamble_settle(ctx)
     // }
 
+    pub fn roll_reward_multiplier_commit(ctx: Context<RollRewardMultiplierCommit>) -> Result<()> {
+        instructions::roll_reward_multiplier_commit(ctx)
+    }
+
+    pub fn roll_reward_multiplier_settle(ctx: Context<RollRewardMultiplierSettle>) -> Result<()> {
+        instructions::roll_reward_multiplier_settle(ctx)
+    }
+
+    pub fn audit_supply(ctx: Context<AuditSupply>) -> Result<()> {
+        instructions::audit_supply(ctx)
+    }
+    pub fn preview_player(ctx: Context<PreviewPlayer>) -> Result<PlayerPreview> {
+        instructions::preview_player(ctx)
+    }
+
     pub fn cancel_pending_action(ctx: Context<CancelPendingAction>) -> Result<()> {
         instructions::cancel_pending_action(ctx)
     }
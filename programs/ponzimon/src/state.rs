@@ -59,7 +59,390 @@ pub struct GlobalState {
 
     /* ── future expansion ───────────────────────── */
     pub rewards_vault: Pubkey,
-    pub padding: [u8; 32], // Reserved space for future fields
+    pub halving_interval: u64, // Slots between reward rate halvings, 0 for a flat rate
+
+    /* ── reward multiplier roll ─────────────────── */
+    pub reward_multiplier_min: u32, // Lower bound of a rolled multiplier (scaled by 1000)
+    pub reward_multiplier_max: u32, // Upper bound of a rolled multiplier (scaled by 1000)
+    pub rate_update_cooldown_slots: u64, // Minimum slots between multiplier rolls
+
+    /* ── supply accounting ──────────────────────── */
+    pub total_rewards_claimed: u64, // Mining rewards paid out of the rewards vault
+    pub cumulative_staking_rewards: u64, // Staking token rewards ever accrued
+    pub total_staking_rewards_claimed: u64, // Staking token rewards paid out of the rewards vault
+
+    /* ── accumulator remainder ──────────────────── */
+    pub acc_reward_remainder: u64, // Scaled reward (× ACC_SCALE) not yet folded into acc_tokens_per_hashpower
+
+    /* ── idle emission ──────────────────────────── */
+    pub idle_emission_policy: u8, // IDLE_EMISSION_DROP / _ROLLOVER / _TREASURY
+    pub emission_treasury: Pubkey, // Token account receiving idle emission under the treasury policy
+    pub rolled_over_emission: u64, // Idle emission waiting for the next non-zero hashpower period
+    pub treasury_emission_owed: u64, // Idle emission not yet swept to the treasury
+
+    /* ── vesting ────────────────────────────────── */
+    pub vesting_percent: u8, // % of each mining payout locked in the player's VestingAccount (0 = off)
+    pub vesting_duration_slots: u64, // Slots over which a vested payout unlocks linearly
+
+    /* ── mining referrals ───────────────────────── */
+    pub mining_referral_bps: u16, // Referrer's share of each mining claim, paid on top (basis points)
+
+    /* ── card transfers ─────────────────────────── */
+    pub card_transfer_fee: u64, // Token fee per transfer_card, split by burn_rate (0 = free)
+
+    /* ── marketplace ────────────────────────────── */
+    pub marketplace_fee_bps: u16, // Protocol cut of each sale, split by burn_rate (basis points)
+
+    /* ── card levels ────────────────────────────── */
+    pub card_level_curve: [CardLevel; MAX_CARD_LEVEL], // Entry i takes a card from level i to i + 1
+
+    pub padding: [u8; 3], // Reserved space for future fields
+}
+
+impl GlobalState {
+    /// Fields up to last_rate_update_slot, unchanged since launch
+    const LAUNCH_FIELDS_SIZE: usize = 8  /* discriminator */
+        + 32 + 32 + 32          /* authority + mint + fees_wallet */
+        + 8  + 8                /* total_supply + burned_tokens */
+        + 8  + 8                /* cumulative_rewards + start_slot */
+        + 8  + 16 + 8           /* reward_rate + acc_tokens_per_hashpower + last_reward_slot */
+        + 1  + 1 + 1 + 8 + 8    /* burn_rate + referral_fee + prod + cooldown + dust_divisor */
+        + 8 + 8 + 8             /* initial_farm_purchase_fee_lamports + booster_pack_cost_microtokens + gamble_fee_lamports */
+        + 8 + 8                 /* total_berries + total_hashpower */
+        + 8 + 8                 /* total_global_gambles + total_global_gamble_wins */
+        + 8 + 8 + 8             /* total_booster_packs_opened + total_card_recycling_attempts + total_successful_card_recycling */
+        + 32 + 8 + 8 + 16 + 16 + 8 + 8 + 8 /* staking: sol_rewards_wallet + total_staked_tokens + staking_lockup_slots + acc_sol_rewards_per_token + acc_token_rewards_per_token + last_staking_reward_slot + token_reward_rate + total_sol_deposited */
+        + 8 + 8; /* dynamic rewards: reward_rate_multiplier + last_rate_update_slot */
+
+    /// Account size, discriminator included
+    pub const SIZE: usize = Self::LAUNCH_FIELDS_SIZE
+        + 32 + 8                /* rewards_vault + halving_interval */
+        + 4 + 4 + 8             /* reward_multiplier_min + reward_multiplier_max + rate_update_cooldown_slots */
+        + 8 + 8 + 8             /* supply accounting: total_rewards_claimed + cumulative_staking_rewards + total_staking_rewards_claimed */
+        + 8                     /* acc_reward_remainder */
+        + 1 + 32 + 8 + 8        /* idle emission: idle_emission_policy + emission_treasury + rolled_over_emission + treasury_emission_owed */
+        + 1 + 8                 /* vesting: vesting_percent + vesting_duration_slots */
+        + 2                     /* mining_referral_bps */
+        + 8                     /* card_transfer_fee */
+        + 2                     /* marketplace_fee_bps */
+        + MAX_CARD_LEVEL * CARD_LEVEL_SIZE /* card_level_curve */
+        + 3; /* padding for future expansion */
+
+    /// Size of accounts created at launch, when rewards_vault and 32 bytes of
+    /// padding ended the account. The halving and multiplier roll fields fit in
+    /// that padding; the supply counters were the first that did not, so every
+    /// launch account needs `migrate_global_state` before it can be loaded.
+    pub const LEGACY_SIZE: usize = Self::LAUNCH_FIELDS_SIZE + 32 + 32;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CardLevel {
+    pub xp_required: u32, // Cumulative XP to reach this level, 0 ends the curve
+    pub hashpower_bonus_bps: u16, // Hashpower increase on reaching this level (basis points)
+    pub berry_increase: u8, // Added berry_consumption on reaching this level
+}
+
+impl CardLevel {
+    pub fn default_curve() -> [CardLevel; MAX_CARD_LEVEL] {
+        DEFAULT_CARD_LEVEL_CURVE.map(|(xp_required, hashpower_bonus_bps, berry_increase)| {
+            CardLevel {
+                xp_required,
+                hashpower_bonus_bps,
+                berry_increase,
+            }
+        })
+    }
+}
+
+/// Checks the active part of a level curve, up to its first zero entry
+pub fn validate_card_level_curve(curve: &[CardLevel]) -> Result<()> {
+    let mut previous_xp = 0;
+    for level in curve.iter().take_while(|level| level.xp_required > 0) {
+        require!(
+            level.xp_required > previous_xp
+                && level.hashpower_bonus_bps <= MAX_CARD_LEVEL_BONUS_BPS,
+            PonzimonError::InvalidCardLevel
+        );
+        previous_xp = level.xp_required;
+    }
+    Ok(())
+}
+
+/// Pending permissionless roll of `GlobalState::reward_rate_multiplier`
+#[account]
+pub struct RewardMultiplierRoll {
+    pub randomness_account: Pubkey, // Switchboard randomness committed for the roll
+    pub commit_slot: u64,           // Seed slot of the commitment, 0 when no roll is pending
+}
+
+#[account]
+pub struct EmissionSchedule {
+    pub token_mint: Pubkey,
+    pub segment_count: u8, // Number of valid entries in `segments`
+    pub segments: [EmissionSegment; MAX_EMISSION_SEGMENTS], // Ordered by start_slot
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct EmissionSegment {
+    pub start_slot: u64,
+    pub rate_per_slot: u64,
+    pub end_slot: Option<u64>, // None = runs until the next segment starts
+}
+
+/// Helper functions for walking the emission schedule
+impl EmissionSchedule {
+    pub fn is_active(&self) -> bool {
+        self.segment_count > 0
+    }
+
+    /// First slot governed by the schedule; earlier slots use the legacy reward_rate.
+    pub fn first_start_slot(&self) -> Option<u64> {
+        if self.is_active() {
+            Some(self.segments[0].start_slot)
+        } else {
+            None
+        }
+    }
+
+    /// Rate of the segment governing `slot`, or None before the schedule starts.
+    /// Gaps between segments have a rate of zero.
+    pub fn rate_at(&self, slot: u64) -> Option<u64> {
+        match self.first_start_slot() {
+            Some(first) if slot >= first => {}
+            _ => return None,
+        }
+        let mut rate = 0;
+        for i in 0..self.segment_count as usize {
+            let segment = &self.segments[i];
+            if slot < segment.start_slot {
+                break;
+            }
+            let ended = segment.end_slot.is_some_and(|end_slot| slot >= end_slot);
+            rate = if ended { 0 } else { segment.rate_per_slot };
+        }
+        Some(rate)
+    }
+
+    /// Appends a segment. Only future segments may be added, so slots that
+    /// have already been emitted can never be repriced.
+    pub fn add_segment(&mut self, segment: EmissionSegment, current_slot: u64) -> Result<()> {
+        require!(
+            (self.segment_count as usize) < MAX_EMISSION_SEGMENTS,
+            PonzimonError::EmissionScheduleFull
+        );
+        require!(
+            segment.start_slot > current_slot,
+            PonzimonError::InvalidEmissionSegment
+        );
+        if let Some(end_slot) = segment.end_slot {
+            require!(
+                end_slot > segment.start_slot,
+                PonzimonError::InvalidEmissionSegment
+            );
+        }
+        if self.segment_count > 0 {
+            let last = &self.segments[(self.segment_count - 1) as usize];
+            require!(
+                segment.start_slot > last.start_slot
+                    && segment.start_slot >= last.end_slot.unwrap_or(0),
+                PonzimonError::InvalidEmissionSegment
+            );
+        }
+        self.segments[self.segment_count as usize] = segment;
+        self.segment_count += 1;
+        Ok(())
+    }
+
+    /// Emission between `from_slot` and `to_slot` under the schedule. A segment
+    /// runs until its end slot or the next segment's start, whichever is first;
+    /// gaps between segments emit nothing. The multiplier is applied per segment.
+    pub fn emission_between(
+        &self,
+        from_slot: u64,
+        to_slot: u64,
+        reward_rate_multiplier: u64,
+    ) -> u128 {
+        let mut reward: u128 = 0;
+        for i in 0..self.segment_count as usize {
+            let segment = &self.segments[i];
+            let mut segment_end = segment.end_slot.unwrap_or(u64::MAX);
+            if i + 1 < self.segment_count as usize {
+                segment_end = segment_end.min(self.segments[i + 1].start_slot);
+            }
+
+            let overlap_start = from_slot.max(segment.start_slot);
+            let overlap_end = to_slot.min(segment_end);
+            if overlap_end <= overlap_start {
+                continue;
+            }
+
+            let rate = (segment.rate_per_slot as u128)
+                .saturating_mul(reward_rate_multiplier as u128)
+                / REWARD_RATE_MULTIPLIER_SCALE as u128;
+            reward =
+                reward.saturating_add(rate.saturating_mul((overlap_end - overlap_start) as u128));
+        }
+        reward
+    }
+}
+
+#[account]
+pub struct RateHistory {
+    pub token_mint: Pubkey,
+    pub head: u16,  // Index the next entry is written to
+    pub count: u16, // Number of valid entries (saturates at RATE_HISTORY_LEN)
+    pub entries: [RateHistoryEntry; RATE_HISTORY_LEN],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RateHistoryEntry {
+    pub slot: u64,
+    pub reward_rate: u64, // Base rate in force at `slot` (after halvings / schedule)
+    pub reward_rate_multiplier: u64,
+    pub acc_tokens_per_hashpower: u128,
+    pub total_hashpower: u64,
+}
+
+/// Helper functions for the rate history ring buffer
+impl RateHistory {
+    pub fn latest(&self) -> Option<&RateHistoryEntry> {
+        if self.count == 0 {
+            return None;
+        }
+        let index = (self.head as usize + RATE_HISTORY_LEN - 1) % RATE_HISTORY_LEN;
+        Some(&self.entries[index])
+    }
+
+    /// Appends an entry, overwriting the oldest once the buffer is full
+    pub fn push(&mut self, entry: RateHistoryEntry) {
+        self.entries[self.head as usize] = entry;
+        self.head = ((self.head as usize + 1) % RATE_HISTORY_LEN) as u16;
+        if (self.count as usize) < RATE_HISTORY_LEN {
+            self.count += 1;
+        }
+    }
+
+    /// Appends an entry only if the rate or multiplier differs from the latest one
+    pub fn record_if_changed(&mut self, entry: RateHistoryEntry) -> bool {
+        if let Some(latest) = self.latest() {
+            if latest.reward_rate == entry.reward_rate
+                && latest.reward_rate_multiplier == entry.reward_rate_multiplier
+            {
+                return false;
+            }
+        }
+        self.push(entry);
+        true
+    }
+}
+
+#[account]
+pub struct Referrer {
+    pub wallet: Pubkey,
+    pub token_mint: Pubkey,
+    pub referee_count: u64, // Players who bought their initial farm through this referrer
+    pub total_sol_earned: u64, // Farm purchase commissions (lamports)
+    pub booster_tokens_earned: u64, // Booster pack commissions from direct referees (microtokens)
+    pub last_referral_slot: u64, // Slot of the most recent new referee
+}
+
+#[account]
+pub struct ReferralCode {
+    pub owner: Pubkey, // Wallet credited as referrer when the code is used
+    pub token_mint: Pubkey,
+    pub code: String, // Lowercase a-z, 0-9 and '_', MIN..=MAX_REFERRAL_CODE_LEN bytes
+    pub created_slot: u64,
+}
+
+#[account]
+pub struct ReferralConfig {
+    pub token_mint: Pubkey,
+    pub level_count: u8, // 0 = single level paid at GlobalState.referral_fee
+    pub level_bps: [u16; MAX_REFERRAL_LEVELS], // Share of the fee paid to each level, direct referrer first
+}
+
+/// Helper functions for referral tiers
+impl ReferralConfig {
+    /// Number of referral levels paid, including the direct referrer
+    pub fn depth(&self) -> usize {
+        (self.level_count as usize).clamp(1, MAX_REFERRAL_LEVELS)
+    }
+
+    /// Commission owed to `level` (0 = direct referrer) out of `fee`. Without
+    /// configured tiers the direct referrer gets `legacy_percent` of the fee.
+    pub fn level_share(&self, fee: u64, level: usize, legacy_percent: u8) -> u64 {
+        if self.level_count == 0 {
+            return if level == 0 {
+                fee.saturating_mul(legacy_percent as u64)
+                    .saturating_div(100)
+            } else {
+                0
+            };
+        }
+        if level >= self.depth() {
+            return 0;
+        }
+        ((fee as u128 * self.level_bps[level] as u128) / 10_000) as u64
+    }
+}
+
+#[account]
+pub struct VestingAccount {
+    pub owner: Pubkey,
+    pub locked_amount: u64,   // Still locked as of `last_update_slot`
+    pub unlocked_amount: u64, // Unlocked and not yet withdrawn
+    pub last_update_slot: u64,
+    pub end_slot: u64, // Slot at which `locked_amount` is fully unlocked
+    pub total_vested: u64,
+    pub total_withdrawn: u64,
+}
+
+/// Helper functions for linear vesting
+impl VestingAccount {
+    /// Moves the share of `locked_amount` that unlocked since the last update
+    /// into `unlocked_amount`
+    pub fn unlock(&mut self, slot: u64) {
+        if slot <= self.last_update_slot {
+            return;
+        }
+        if slot >= self.end_slot {
+            self.unlocked_amount = self.unlocked_amount.saturating_add(self.locked_amount);
+            self.locked_amount = 0;
+        } else {
+            let elapsed = (slot - self.last_update_slot) as u128;
+            let remaining = (self.end_slot - self.last_update_slot) as u128;
+            let released = (self.locked_amount as u128 * elapsed / remaining) as u64;
+            self.locked_amount -= released;
+            self.unlocked_amount = self.unlocked_amount.saturating_add(released);
+        }
+        self.last_update_slot = slot;
+    }
+
+    /// Locks `amount` to unlock linearly over `duration` slots. Tokens already
+    /// locked are merged in by moving the end slot to the amount-weighted
+    /// average of both schedules.
+    pub fn deposit(&mut self, amount: u64, slot: u64, duration: u64) {
+        self.unlock(slot);
+
+        let existing_remaining = self.end_slot.saturating_sub(slot) as u128;
+        let total_locked = self.locked_amount as u128 + amount as u128;
+        let weighted =
+            self.locked_amount as u128 * existing_remaining + amount as u128 * duration as u128;
+        if let Some(weighted_remaining) = weighted.checked_div(total_locked) {
+            self.end_slot = slot.saturating_add(weighted_remaining as u64);
+        }
+        self.locked_amount = self.locked_amount.saturating_add(amount);
+        self.total_vested = self.total_vested.saturating_add(amount);
+    }
+
+    /// Releases everything unlocked so far and returns the amount
+    pub fn withdraw(&mut self, slot: u64) -> u64 {
+        self.unlock(slot);
+        let amount = self.unlocked_amount;
+        self.unlocked_amount = 0;
+        self.total_withdrawn = self.total_withdrawn.saturating_add(amount);
+        amount
+    }
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
@@ -73,6 +456,11 @@ pub enum PendingRandomAction {
         card_indices: [u8; 128], // Array of card indices to recycle
         card_count: u8,          // Number of valid indices in the array
     },
+    RecycleVault {
+        page: u8, // CardVault page holding the cards
+        card_serials: [u32; MAX_VAULT_RECYCLE_CARDS],
+        card_count: u8, // Number of valid serials in the array
+    },
 }
 
 impl Default for PendingRandomAction {
@@ -118,13 +506,84 @@ pub struct Player {
     pub last_acc_token_rewards_per_token: u128,
     pub claimed_token_rewards: u64,
 
+    /* ── mining rewards remainder ───────────────── */
+    pub acc_reward_remainder: u64, // Sub-token mining reward (× ACC_SCALE) carried to the next claim
+
+    /* ── claim delegation ───────────────────────── */
+    pub claim_destination: Option<Pubkey>, // Token account keeper claims are paid into
+    pub claim_keeper: Option<Pubkey>,      // Key allowed to trigger claims on the player's behalf
+    pub next_card_serial: u32,             // Last serial handed out; serials are never reused
+
+    /* ── mining referrals ───────────────────────── */
+    pub referral_rewards_owed: u64, // Referrer's mining share held back while no valid token account was given
+
+    /* ── card transfers ─────────────────────────── */
+    pub accepts_card_transfers: bool, // Set by the player to receive cards through transfer_card
+
     /* ── future expansion ───────────────────────── */
-    pub padding: [u8; 64], // Reserved space for future fields
+    pub padding: [u8; 43], // Reserved space for future fields
 }
 
 /// Helper functions for working with fixed-size arrays
 impl Player {
-    pub fn add_card(&mut self, card: Card) -> Result<()> {
+    /// Fields from card_count to claimed_token_rewards, unchanged since launch
+    const LAUNCH_STATS_SIZE: usize = 1 // card_count: u8
+        + 16       // staked_cards_bitset: u128
+        + 8 + 8    // berries + total_hashpower: u64
+        + 33       // referrer: Option<Pubkey> (1+32)
+        + 16       // last_acc_tokens_per_hashpower: u128
+        + 8 + 8 + 8 // last_claim_slot + last_upgrade_slot + total_rewards: u64
+        + 8 + 8    // total_gambles + total_gamble_wins: u64
+        + 130      // pending_action: PendingRandomAction enum (1 byte disc + 129 for largest Recycle variant)
+        + 32 + 8   // randomness_account + commit_slot
+        + 8 * 6    // total_earnings_for_referrer .. total_tokens_spent: u64
+        + 8 + 8 + 16 + 16 + 8; // Staking stats: staked_tokens + last_stake_slot + last_acc_sol_rewards_per_token + last_acc_token_rewards_per_token + claimed_token_rewards
+
+    /// Account size, discriminator included
+    pub const SIZE: usize = 8 // discriminator
+        + 32       // owner: Pubkey
+        + 10       // farm: Farm (1+1+8)
+        + (MAX_CARDS_PER_PLAYER as usize * 15) // cards: [Card; MAX_CARDS_PER_PLAYER] - Card = 15 bytes (2+1+2+1+4+1+4)
+        + Self::LAUNCH_STATS_SIZE
+        + 8        // acc_reward_remainder: u64
+        + 33 + 33  // claim_destination + claim_keeper: Option<Pubkey>
+        + 4        // next_card_serial: u32
+        + 8        // referral_rewards_owed: u64
+        + 1        // accepts_card_transfers: bool
+        + 43; // padding: [u8; 43] for future expansion
+
+    /// Size of accounts created at launch, with 6-byte cards and 64 bytes of
+    /// padding. The reward remainder fit in that padding; claim delegation was
+    /// the first change that did not, and later card fields grew every entry of
+    /// `cards`. `migrate_player` rewrites these into the current layout.
+    pub const LEGACY_SIZE: usize = 8 + 32 + 10
+        + (MAX_CARDS_PER_PLAYER as usize * 6) // cards: [LegacyCard; MAX_CARDS_PER_PLAYER]
+        + Self::LAUNCH_STATS_SIZE
+        + 64;
+
+    /// Appends a card under a fresh serial and returns the serial
+    pub fn add_card(&mut self, mut card: Card) -> Result<u32> {
+        require!(
+            (self.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
+            PonzimonError::MachineCapacityExceeded
+        );
+        self.assign_serial(&mut card)?;
+        self.push_card(card)?;
+        Ok(card.serial)
+    }
+
+    /// Gives a card that is new to this player the next serial
+    pub fn assign_serial(&mut self, card: &mut Card) -> Result<()> {
+        self.next_card_serial = self
+            .next_card_serial
+            .checked_add(1)
+            .ok_or(PonzimonError::ArithmeticOverflow)?;
+        card.serial = self.next_card_serial;
+        Ok(())
+    }
+
+    /// Appends one of this player's own cards, keeping its serial
+    pub fn push_card(&mut self, card: Card) -> Result<()> {
         require!(
             (self.card_count as usize) < MAX_CARDS_PER_PLAYER as usize,
             PonzimonError::MachineCapacityExceeded
@@ -134,6 +593,48 @@ impl Player {
         Ok(())
     }
 
+    /// Current index of the card with `serial`
+    pub fn find_card_by_serial(&self, serial: u32) -> Result<u8> {
+        self.cards[..self.card_count as usize]
+            .iter()
+            .position(|card| serial != 0 && card.serial == serial)
+            .map(|index| index as u8)
+            .ok_or_else(|| error!(PonzimonError::CardSerialNotFound))
+    }
+
+    /// Catalog id of the card with `serial`, or 0 if the player does not hold it
+    pub fn card_id_by_serial(&self, serial: u32) -> u16 {
+        self.find_card_by_serial(serial)
+            .map_or(0, |index| self.cards[index as usize].id)
+    }
+
+    /// Removes the unstaked, not-recycling cards with `serials` and returns
+    /// them in the order requested
+    pub fn take_cards(&mut self, serials: &[u32]) -> Result<Vec<Card>> {
+        let mut indices = Vec::with_capacity(serials.len());
+        for &serial in serials {
+            let index = self.find_card_by_serial(serial)?;
+            require!(
+                !indices.contains(&index),
+                PonzimonError::DuplicateCardSerials
+            );
+            require!(!self.is_card_staked(index), PonzimonError::CardIsStaked);
+            require!(
+                !self.is_card_being_recycled(index),
+                PonzimonError::CardPendingRecycling
+            );
+            indices.push(index);
+        }
+        let cards: Vec<Card> = indices.iter().map(|&i| self.cards[i as usize]).collect();
+
+        // Highest index first so earlier removals don't shift later ones
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        for index in indices {
+            self.remove_card(index)?;
+        }
+        Ok(cards)
+    }
+
     pub fn is_card_being_recycled(&self, card_index: u8) -> bool {
         if let PendingRandomAction::Recycle {
             card_indices,
@@ -149,6 +650,18 @@ impl Player {
         false
     }
 
+    pub fn is_vault_card_being_recycled(&self, vault_page: u8, serial: u32) -> bool {
+        if let PendingRandomAction::RecycleVault {
+            page,
+            card_serials,
+            card_count,
+        } = &self.pending_action
+        {
+            return *page == vault_page && card_serials[..*card_count as usize].contains(&serial);
+        }
+        false
+    }
+
     pub fn remove_card(&mut self, index: u8) -> Result<()> {
         let index_usize = index as usize;
         require!(
@@ -186,6 +699,19 @@ impl Player {
 
         self.staked_cards_bitset = new_bitset;
 
+        // Keep a pending recycle pointing at the same cards
+        if let PendingRandomAction::Recycle {
+            card_indices,
+            card_count,
+        } = &mut self.pending_action
+        {
+            for pending_index in card_indices[..*card_count as usize].iter_mut() {
+                if *pending_index > index {
+                    *pending_index -= 1;
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -196,6 +722,10 @@ impl Player {
             self.staked_cards_bitset & mask == 0,
             PonzimonError::CardIsStaked
         );
+        require!(
+            self.count_staked_cards() < MAX_STAKED_CARDS_PER_PLAYER,
+            PonzimonError::TooManyStakedCards
+        );
         self.staked_cards_bitset |= mask;
         Ok(())
     }
@@ -222,6 +752,53 @@ impl Player {
         self.staked_cards_bitset.count_ones() as u8
     }
 
+    /// Adds `slots` XP to every staked card and applies the level-ups `curve`
+    /// allows, keeping `berries` and `total_hashpower` in step. A level-up that
+    /// would push berries past the farm's capacity waits until there is room.
+    /// Returns the indices of the cards that levelled up.
+    pub fn accrue_card_xp(&mut self, slots: u64, curve: &[CardLevel]) -> Vec<u8> {
+        let xp = slots.min(u32::MAX as u64) as u32;
+        let mut levelled = Vec::new();
+        for index in 0..self.card_count {
+            if !self.is_card_staked(index) {
+                continue;
+            }
+            let card = &mut self.cards[index as usize];
+            card.xp = card.xp.saturating_add(xp);
+
+            let start_level = card.level;
+            while let Some(next) = curve
+                .get(card.level as usize)
+                .filter(|next| next.xp_required > 0 && card.xp >= next.xp_required)
+            {
+                let berry_consumption = card.berry_consumption.saturating_add(next.berry_increase);
+                let berries = self.berries + (berry_consumption - card.berry_consumption) as u64;
+                if berries > self.farm.berry_capacity {
+                    break;
+                }
+                let hashpower =
+                    (card.hashpower as u64 * (10_000 + next.hashpower_bonus_bps as u64) / 10_000)
+                        .min(u16::MAX as u64);
+                self.total_hashpower += hashpower - card.hashpower as u64;
+                self.berries = berries;
+                card.hashpower = hashpower as u16;
+                card.berry_consumption = berry_consumption;
+                card.level += 1;
+            }
+            if card.level > start_level {
+                levelled.push(index);
+            }
+        }
+        levelled
+    }
+
+    /// Refreshes the cached farm limits from the config, picking up any rebalance
+    /// of the player's level
+    pub fn sync_farm(&mut self, config: &FarmConfig) -> Result<()> {
+        self.farm = config.farm(self.farm.farm_type)?;
+        Ok(())
+    }
+
     pub fn calculate_total_berry_consumption(&self) -> u64 {
         let mut total = 0u64;
         for i in 0..self.card_count {
@@ -241,12 +818,316 @@ pub struct Farm {
     pub berry_capacity: u64, // Total berry capacity of this farm
 }
 
+/// Player layout at launch, only read by `migrate_player`
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyPlayer {
+    pub owner: Pubkey,
+    pub farm: Farm,
+    pub cards: [LegacyCard; MAX_CARDS_PER_PLAYER as usize],
+    pub card_count: u8,
+    pub staked_cards_bitset: u128,
+    pub berries: u64,
+    pub total_hashpower: u64,
+    pub referrer: Option<Pubkey>,
+    pub last_acc_tokens_per_hashpower: u128,
+    pub last_claim_slot: u64,
+    pub last_upgrade_slot: u64,
+    pub total_rewards: u64,
+    pub total_gambles: u64,
+    pub total_gamble_wins: u64,
+    pub pending_action: PendingRandomAction, // Launch variants are unchanged
+    pub randomness_account: Pubkey,
+    pub commit_slot: u64,
+    pub total_earnings_for_referrer: u64,
+    pub total_booster_packs_opened: u64,
+    pub total_cards_recycled: u64,
+    pub successful_card_recycling: u64,
+    pub total_sol_spent: u64,
+    pub total_tokens_spent: u64,
+    pub staked_tokens: u64,
+    pub last_stake_slot: u64,
+    pub last_acc_sol_rewards_per_token: u128,
+    pub last_acc_token_rewards_per_token: u128,
+    pub claimed_token_rewards: u64,
+    pub padding: [u8; 64],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LegacyCard {
+    pub id: u16,
+    pub rarity: u8,
+    pub hashpower: u16,
+    pub berry_consumption: u8,
+}
+
+impl LegacyPlayer {
+    /// Converts to the current layout. Owned cards get serials 1..=card_count in
+    /// inventory order; other fields added since launch start empty.
+    pub fn into_player(self) -> Player {
+        let mut cards = [Card::default(); MAX_CARDS_PER_PLAYER as usize];
+        for (index, (card, legacy)) in cards
+            .iter_mut()
+            .zip(self.cards.iter())
+            .take(self.card_count as usize)
+            .enumerate()
+        {
+            *card = Card {
+                id: legacy.id,
+                rarity: legacy.rarity,
+                hashpower: legacy.hashpower,
+                berry_consumption: legacy.berry_consumption,
+                serial: index as u32 + 1,
+                ..Card::default()
+            };
+        }
+        Player {
+            owner: self.owner,
+            farm: self.farm,
+            cards,
+            card_count: self.card_count,
+            staked_cards_bitset: self.staked_cards_bitset,
+            berries: self.berries,
+            total_hashpower: self.total_hashpower,
+            referrer: self.referrer,
+            last_acc_tokens_per_hashpower: self.last_acc_tokens_per_hashpower,
+            last_claim_slot: self.last_claim_slot,
+            last_upgrade_slot: self.last_upgrade_slot,
+            total_rewards: self.total_rewards,
+            total_gambles: self.total_gambles,
+            total_gamble_wins: self.total_gamble_wins,
+            pending_action: self.pending_action,
+            randomness_account: self.randomness_account,
+            commit_slot: self.commit_slot,
+            total_earnings_for_referrer: self.total_earnings_for_referrer,
+            total_booster_packs_opened: self.total_booster_packs_opened,
+            total_cards_recycled: self.total_cards_recycled,
+            successful_card_recycling: self.successful_card_recycling,
+            total_sol_spent: self.total_sol_spent,
+            total_tokens_spent: self.total_tokens_spent,
+            staked_tokens: self.staked_tokens,
+            last_stake_slot: self.last_stake_slot,
+            last_acc_sol_rewards_per_token: self.last_acc_sol_rewards_per_token,
+            last_acc_token_rewards_per_token: self.last_acc_token_rewards_per_token,
+            claimed_token_rewards: self.claimed_token_rewards,
+            acc_reward_remainder: 0,
+            claim_destination: None,
+            claim_keeper: None,
+            next_card_serial: self.card_count as u32,
+            referral_rewards_owed: 0,
+            accepts_card_transfers: false,
+            padding: [0; 43],
+        }
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
 pub struct Card {
     pub id: u16,               // Card ID from the Pokemon card list
     pub rarity: u8, // Card rarity (0=Common, 1=Uncommon, 2=Rare, 3=VeryRare, 4=SuperRare, 5=MegaRare)
     pub hashpower: u16, // Hashpower level of the card for rewards (max 65535 is enough)
     pub berry_consumption: u8, // How many berries this card consumes per slot (max 255 is enough)
+    pub serial: u32, // Unique per player, assigned by Player::add_card or migrate_player (0 = unassigned)
+    pub level: u8,   // Raised by staking XP along GlobalState::card_level_curve
+    pub xp: u32,     // Slots spent staked, kept across transfers
+}
+
+#[account]
+pub struct CardCatalog {
+    pub token_mint: Pubkey,
+    pub cards: Vec<CatalogCard>, // Sorted by id, at most MAX_CATALOG_CARDS entries
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CatalogCard {
+    pub id: u16,
+    pub rarity: u8,
+    pub hashpower: u16,
+    pub berry_consumption: u8,
+    pub enabled: bool,          // Disabled cards are never handed out
+    pub booster_eligible: bool, // Can be drawn from boosters and recycling
+}
+
+/// Helper functions for the card catalog
+impl CardCatalog {
+    pub fn get(&self, id: u16) -> Option<&CatalogCard> {
+        self.cards
+            .binary_search_by_key(&id, |card| card.id)
+            .ok()
+            .map(|index| &self.cards[index])
+    }
+
+    pub fn get_mut(&mut self, id: u16) -> Option<&mut CatalogCard> {
+        self.cards
+            .binary_search_by_key(&id, |card| card.id)
+            .ok()
+            .map(move |index| &mut self.cards[index])
+    }
+
+    /// Inserts a new entry, keeping the catalog sorted by id
+    pub fn add(&mut self, card: CatalogCard) -> Result<()> {
+        require!(card.rarity <= MEGA_RARE, PonzimonError::InvalidCardRarity);
+        require!(
+            self.cards.len() < MAX_CATALOG_CARDS,
+            PonzimonError::CardCatalogFull
+        );
+        match self.cards.binary_search_by_key(&card.id, |c| c.id) {
+            Ok(_) => err!(PonzimonError::DuplicateCatalogCard),
+            Err(index) => {
+                self.cards.insert(index, card);
+                Ok(())
+            }
+        }
+    }
+
+    /// Enabled, booster-eligible cards of `rarity`, in id order
+    pub fn drawable_of_rarity(&self, rarity: u8) -> Vec<&CatalogCard> {
+        self.cards
+            .iter()
+            .filter(|card| card.enabled && card.booster_eligible && card.rarity == rarity)
+            .collect()
+    }
+}
+
+impl CatalogCard {
+    pub fn to_card(&self) -> Card {
+        Card {
+            id: self.id,
+            rarity: self.rarity,
+            hashpower: self.hashpower,
+            berry_consumption: self.berry_consumption,
+            serial: 0,
+            level: 0,
+            xp: 0,
+        }
+    }
+}
+
+#[account]
+pub struct FarmConfig {
+    pub token_mint: Pubkey,
+    pub levels: Vec<FarmLevel>, // Indexed by farm_type; level 0 is the state before buying a farm
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct FarmLevel {
+    pub total_cards: u8,     // Max number of cards staked at this level
+    pub berry_capacity: u64, // Total berry capacity at this level
+    pub cost: u64,           // Microtokens to upgrade into this level
+}
+
+impl FarmConfig {
+    pub fn level(&self, farm_type: u8) -> Result<&FarmLevel> {
+        self.levels
+            .get(farm_type as usize)
+            .ok_or(PonzimonError::InvalidFarmType.into())
+    }
+
+    pub fn farm(&self, farm_type: u8) -> Result<Farm> {
+        let level = self.level(farm_type)?;
+        Ok(Farm {
+            farm_type,
+            total_cards: level.total_cards,
+            berry_capacity: level.berry_capacity,
+        })
+    }
+
+    /// Replaces an existing level or appends the next one
+    pub fn set_level(&mut self, farm_type: u8, level: FarmLevel) -> Result<()> {
+        require!(farm_type > 0, PonzimonError::InvalidFarmType);
+        require!(
+            level.total_cards <= MAX_STAKED_CARDS_PER_PLAYER,
+            PonzimonError::InvalidFarmLevel
+        );
+        let index = farm_type as usize;
+        match index.cmp(&self.levels.len()) {
+            std::cmp::Ordering::Less => self.levels[index] = level,
+            std::cmp::Ordering::Equal => {
+                require!(
+                    self.levels.len() < MAX_FARM_LEVELS,
+                    PonzimonError::FarmConfigFull
+                );
+                self.levels.push(level);
+            }
+            std::cmp::Ordering::Greater => return err!(PonzimonError::InvalidFarmType),
+        }
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Listing {
+    pub seller: Pubkey, // Seller's wallet, paid on purchase and refunded the rent
+    pub token_mint: Pubkey,
+    pub card: Card, // Escrowed card, keeping the seller's serial
+    pub price: u64, // Microtokens
+    pub created_slot: u64,
+}
+
+#[account]
+pub struct SwapOffer {
+    pub maker: Pubkey, // Maker's wallet, receives the taker's cards and tokens
+    pub token_mint: Pubkey,
+    pub offer_id: u64,            // Maker-chosen id, part of the PDA seeds
+    pub offered_cards: Vec<Card>, // Escrowed, at most MAX_SWAP_CARDS
+    pub wanted: Vec<SwapWant>,    // One taker card per entry, at most MAX_SWAP_CARDS
+    pub wanted_tokens: u64,       // Paid by the taker on top of the cards (0 = none)
+    pub created_slot: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwapWant {
+    CardId(u16),
+    Rarity(u8),
+}
+
+impl SwapWant {
+    pub fn matches(&self, card: &Card) -> bool {
+        match *self {
+            SwapWant::CardId(id) => card.id == id,
+            SwapWant::Rarity(rarity) => card.rarity == rarity,
+        }
+    }
+}
+
+#[account]
+pub struct CardMintRecord {
+    pub card_mint: Pubkey, // Supply-1, 0-decimal mint representing the card
+    pub token_mint: Pubkey,
+    pub card: Card,          // Stats restored on import; serial is the exporter's
+    pub exported_by: Pubkey, // Exporter's wallet, refunded the rent on import
+    pub exported_slot: u64,
+}
+
+#[account]
+pub struct CardVault {
+    pub player: Pubkey,
+    pub page: u8,
+    pub cards: Vec<Card>, // Unstaked cards, at most CARD_VAULT_PAGE_SIZE; serials are the player's
+}
+
+/// Helper functions for vault pages
+impl CardVault {
+    pub fn contains(&self, serial: u32) -> bool {
+        self.cards.iter().any(|card| card.serial == serial)
+    }
+
+    pub fn push(&mut self, card: Card) -> Result<()> {
+        require!(
+            self.cards.len() < CARD_VAULT_PAGE_SIZE,
+            PonzimonError::CardVaultFull
+        );
+        self.cards.push(card);
+        Ok(())
+    }
+
+    pub fn take(&mut self, serial: u32) -> Result<Card> {
+        let index = self
+            .cards
+            .iter()
+            .position(|card| card.serial == serial)
+            .ok_or(PonzimonError::CardSerialNotFound)?;
+        Ok(self.cards.remove(index))
+    }
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -295,7 +1176,13 @@ mod tests {
             last_acc_sol_rewards_per_token: 0,
             last_acc_token_rewards_per_token: 0,
             claimed_token_rewards: 0,
-            padding: [0; 64],
+            acc_reward_remainder: 0,
+            claim_destination: None,
+            claim_keeper: None,
+            next_card_serial: 0,
+            referral_rewards_owed: 0,
+            accepts_card_transfers: false,
+            padding: [0; 43],
         }
     }
 
@@ -394,4 +1281,664 @@ mod tests {
         assert!(player.is_card_staked(2)); // Was 3, now 2
         assert_eq!(player.count_staked_cards(), 2);
     }
+
+    fn new_schedule() -> EmissionSchedule {
+        EmissionSchedule {
+            token_mint: Pubkey::new_unique(),
+            segment_count: 0,
+            segments: [EmissionSegment::default(); MAX_EMISSION_SEGMENTS],
+        }
+    }
+
+    fn segment(start_slot: u64, rate_per_slot: u64, end_slot: Option<u64>) -> EmissionSegment {
+        EmissionSegment {
+            start_slot,
+            rate_per_slot,
+            end_slot,
+        }
+    }
+
+    #[test]
+    fn test_card_serials_survive_removal() {
+        let mut player = new_player();
+        for _ in 0..4 {
+            player.add_card(Card::default()).unwrap();
+        }
+        assert_eq!(player.find_card_by_serial(3).unwrap(), 2);
+
+        player.remove_card(0).unwrap();
+        assert_eq!(player.find_card_by_serial(3).unwrap(), 1);
+        assert!(player.find_card_by_serial(1).is_err());
+        assert!(player.find_card_by_serial(0).is_err());
+
+        // Serials are never handed out twice
+        assert_eq!(player.add_card(Card::default()).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_remove_card_keeps_pending_recycle_in_place() {
+        let mut player = new_player();
+        for _ in 0..5 {
+            player.add_card(Card::default()).unwrap();
+        }
+        let mut card_indices = [0u8; 128];
+        card_indices[0] = 3;
+        card_indices[1] = 4;
+        player.pending_action = PendingRandomAction::Recycle {
+            card_indices,
+            card_count: 2,
+        };
+
+        player.remove_card(1).unwrap();
+        assert!(player.is_card_being_recycled(2));
+        assert!(player.is_card_being_recycled(3));
+        assert!(!player.is_card_being_recycled(4));
+        assert_eq!(player.cards[2].serial, 4);
+    }
+
+    #[test]
+    fn test_take_cards() {
+        let mut player = new_player();
+        for id in 1..=5 {
+            player
+                .add_card(Card {
+                    id,
+                    ..Card::default()
+                })
+                .unwrap();
+        }
+        player.stake_card(4).unwrap();
+
+        assert!(player.take_cards(&[2, 2]).is_err());
+        assert!(player.take_cards(&[1, 5]).is_err());
+        assert!(player.take_cards(&[9]).is_err());
+        assert_eq!(player.card_count, 5);
+
+        let cards = player.take_cards(&[2, 4, 1]).unwrap();
+        let ids: Vec<u16> = cards.iter().map(|card| card.id).collect();
+        assert_eq!(ids, vec![2, 4, 1]);
+        assert_eq!(player.card_count, 2);
+        assert_eq!(player.cards[0].serial, 3);
+        // The staked card moved down with its stake
+        assert!(player.is_card_staked(1));
+        assert_eq!(player.cards[1].serial, 5);
+    }
+
+    #[test]
+    fn test_card_vault_round_trip() {
+        let mut player = new_player();
+        let mut vault = CardVault {
+            player: Pubkey::new_unique(),
+            page: 0,
+            cards: Vec::new(),
+        };
+        for _ in 0..3 {
+            player.add_card(Card::default()).unwrap();
+        }
+
+        for card in player.take_cards(&[1, 3]).unwrap() {
+            vault.push(card).unwrap();
+        }
+        assert_eq!(player.card_count, 1);
+        assert!(vault.contains(3));
+
+        // Serials survive the round trip
+        let card = vault.take(3).unwrap();
+        player.push_card(card).unwrap();
+        assert_eq!(player.find_card_by_serial(3).unwrap(), 1);
+        assert!(vault.take(3).is_err());
+        assert_eq!(player.add_card(Card::default()).unwrap(), 4);
+
+        while vault.cards.len() < CARD_VAULT_PAGE_SIZE {
+            vault.push(Card::default()).unwrap();
+        }
+        assert!(vault.push(Card::default()).is_err());
+    }
+
+    #[test]
+    fn test_vault_recycle_pending() {
+        let mut player = new_player();
+        let mut card_serials = [0u32; MAX_VAULT_RECYCLE_CARDS];
+        card_serials[0] = 7;
+        player.pending_action = PendingRandomAction::RecycleVault {
+            page: 2,
+            card_serials,
+            card_count: 1,
+        };
+        assert!(player.is_vault_card_being_recycled(2, 7));
+        assert!(!player.is_vault_card_being_recycled(1, 7));
+        assert!(!player.is_vault_card_being_recycled(2, 0));
+    }
+
+    #[test]
+    fn test_swap_want_matches() {
+        let card = Card {
+            id: 42,
+            rarity: RARE,
+            ..Card::default()
+        };
+        assert!(SwapWant::CardId(42).matches(&card));
+        assert!(!SwapWant::CardId(43).matches(&card));
+        assert!(SwapWant::Rarity(RARE).matches(&card));
+        assert!(!SwapWant::Rarity(COMMON).matches(&card));
+    }
+
+    #[test]
+    fn test_emission_schedule_only_accepts_future_segments() {
+        let mut schedule = new_schedule();
+
+        // Must start after the current slot
+        assert!(schedule.add_segment(segment(100, 10, None), 100).is_err());
+        assert!(schedule
+            .add_segment(segment(101, 10, Some(200)), 100)
+            .is_ok());
+
+        // Must not start inside the previous segment
+        assert!(schedule.add_segment(segment(150, 5, None), 100).is_err());
+        // Must end after it starts
+        assert!(schedule
+            .add_segment(segment(300, 5, Some(300)), 100)
+            .is_err());
+        assert!(schedule.add_segment(segment(300, 5, None), 100).is_ok());
+        assert_eq!(schedule.segment_count, 2);
+    }
+
+    #[test]
+    fn test_emission_schedule_walks_segments_and_gaps() {
+        let mut schedule = new_schedule();
+        schedule
+            .add_segment(segment(100, 10, Some(200)), 0)
+            .unwrap();
+        schedule.add_segment(segment(300, 5, None), 0).unwrap();
+        schedule.add_segment(segment(400, 1, None), 0).unwrap();
+
+        // Before the schedule starts nothing is emitted by it
+        assert_eq!(schedule.emission_between(0, 100, 1000), 0);
+        // 100 slots at 10, gap 200..300, 100 slots at 5 (closed by the next start), 100 at 1
+        assert_eq!(schedule.emission_between(0, 500, 1000), 1_000 + 500 + 100);
+        // Partial overlap with the multiplier applied (0.5x)
+        assert_eq!(schedule.emission_between(150, 350, 500), 50 * 5 + 50 * 2);
+    }
+
+    #[test]
+    fn test_emission_schedule_capacity() {
+        let mut schedule = new_schedule();
+        for i in 0..MAX_EMISSION_SEGMENTS as u64 {
+            schedule
+                .add_segment(segment(10 + i * 10, 1, None), 0)
+                .unwrap();
+        }
+        assert_eq!(
+            schedule
+                .add_segment(segment(10_000, 1, None), 0)
+                .unwrap_err(),
+            error!(PonzimonError::EmissionScheduleFull)
+        );
+    }
+
+    #[test]
+    fn test_emission_schedule_rate_at() {
+        let mut schedule = new_schedule();
+        assert_eq!(schedule.rate_at(100), None);
+
+        schedule
+            .add_segment(segment(100, 10, Some(200)), 0)
+            .unwrap();
+        schedule.add_segment(segment(300, 5, None), 0).unwrap();
+
+        assert_eq!(schedule.rate_at(99), None);
+        assert_eq!(schedule.rate_at(100), Some(10));
+        assert_eq!(schedule.rate_at(200), Some(0)); // gap
+        assert_eq!(schedule.rate_at(1_000), Some(5));
+    }
+
+    fn history_entry(slot: u64, reward_rate: u64, reward_rate_multiplier: u64) -> RateHistoryEntry {
+        RateHistoryEntry {
+            slot,
+            reward_rate,
+            reward_rate_multiplier,
+            acc_tokens_per_hashpower: 0,
+            total_hashpower: 0,
+        }
+    }
+
+    #[test]
+    fn test_rate_history_records_changes_only() {
+        let mut history = RateHistory {
+            token_mint: Pubkey::new_unique(),
+            head: 0,
+            count: 0,
+            entries: [RateHistoryEntry::default(); RATE_HISTORY_LEN],
+        };
+        assert!(history.latest().is_none());
+
+        assert!(history.record_if_changed(history_entry(1, 100, 1000)));
+        assert!(!history.record_if_changed(history_entry(2, 100, 1000)));
+        assert!(history.record_if_changed(history_entry(3, 100, 1200)));
+        assert!(history.record_if_changed(history_entry(4, 50, 1200)));
+        assert_eq!(history.count, 3);
+        assert_eq!(history.latest().unwrap().slot, 4);
+    }
+
+    #[test]
+    fn test_rate_history_wraps_around() {
+        let mut history = RateHistory {
+            token_mint: Pubkey::new_unique(),
+            head: 0,
+            count: 0,
+            entries: [RateHistoryEntry::default(); RATE_HISTORY_LEN],
+        };
+        for slot in 0..(RATE_HISTORY_LEN as u64 + 5) {
+            history.push(history_entry(slot, slot, 1000));
+        }
+        assert_eq!(history.count as usize, RATE_HISTORY_LEN);
+        assert_eq!(history.head, 5);
+        assert_eq!(history.latest().unwrap().slot, RATE_HISTORY_LEN as u64 + 4);
+        // Oldest surviving entry sits at the head
+        assert_eq!(history.entries[history.head as usize].slot, 5);
+    }
+
+    fn new_vesting() -> VestingAccount {
+        VestingAccount {
+            owner: Pubkey::new_unique(),
+            locked_amount: 0,
+            unlocked_amount: 0,
+            last_update_slot: 0,
+            end_slot: 0,
+            total_vested: 0,
+            total_withdrawn: 0,
+        }
+    }
+
+    #[test]
+    fn test_vesting_unlocks_linearly() {
+        let mut vesting = new_vesting();
+        vesting.deposit(1_000, 100, 1_000);
+        assert_eq!(vesting.end_slot, 1_100);
+
+        assert_eq!(vesting.withdraw(100), 0);
+        assert_eq!(vesting.withdraw(350), 250);
+        assert_eq!(vesting.withdraw(600), 250);
+        // Past the end everything is released, and only once
+        assert_eq!(vesting.withdraw(5_000), 500);
+        assert_eq!(vesting.withdraw(6_000), 0);
+        assert_eq!(vesting.total_withdrawn, vesting.total_vested);
+    }
+
+    #[test]
+    fn test_vesting_merges_deposits_by_weighted_end() {
+        let mut vesting = new_vesting();
+        vesting.deposit(1_000, 0, 1_000);
+        // Halfway through, 500 remain locked with 500 slots left
+        vesting.deposit(500, 500, 1_000);
+        assert_eq!(vesting.unlocked_amount, 500);
+        assert_eq!(vesting.locked_amount, 1_000);
+        // (500 * 500 + 500 * 1000) / 1000 = 750 slots from now
+        assert_eq!(vesting.end_slot, 1_250);
+
+        assert_eq!(vesting.withdraw(1_250), 1_500);
+        assert_eq!(vesting.locked_amount, 0);
+    }
+
+    fn referral_config(level_bps: &[u16]) -> ReferralConfig {
+        let mut config = ReferralConfig {
+            token_mint: Pubkey::new_unique(),
+            level_count: level_bps.len() as u8,
+            level_bps: [0; MAX_REFERRAL_LEVELS],
+        };
+        config.level_bps[..level_bps.len()].copy_from_slice(level_bps);
+        config
+    }
+
+    #[test]
+    fn test_referral_config_without_tiers_pays_legacy_fee() {
+        let config = referral_config(&[]);
+        assert_eq!(config.depth(), 1);
+        assert_eq!(config.level_share(1_000, 0, 25), 250);
+        assert_eq!(config.level_share(1_000, 1, 25), 0);
+    }
+
+    #[test]
+    fn test_referral_config_tier_shares() {
+        let config = referral_config(&[2_000, 500, 100]);
+        assert_eq!(config.depth(), 3);
+        assert_eq!(config.level_share(10_000, 0, 25), 2_000);
+        assert_eq!(config.level_share(10_000, 1, 25), 500);
+        assert_eq!(config.level_share(10_000, 2, 25), 100);
+        assert_eq!(config.level_share(10_000, 3, 25), 0);
+    }
+
+    fn seeded_catalog() -> CardCatalog {
+        let mut catalog = CardCatalog {
+            token_mint: Pubkey::new_unique(),
+            cards: Vec::new(),
+        };
+        for &(id, rarity, hashpower, berry_consumption) in CARD_DATA.iter() {
+            catalog
+                .add(CatalogCard {
+                    id,
+                    rarity,
+                    hashpower,
+                    berry_consumption,
+                    enabled: true,
+                    booster_eligible: true,
+                })
+                .unwrap();
+        }
+        catalog
+    }
+
+    #[test]
+    fn test_card_catalog_seeds_card_data() {
+        let catalog = seeded_catalog();
+        assert_eq!(catalog.cards.len(), CARD_DATA.len());
+        for &(id, rarity, hashpower, berry_consumption) in CARD_DATA.iter() {
+            let card = catalog.get(id).unwrap();
+            assert_eq!(card.rarity, rarity);
+            assert_eq!(card.hashpower, hashpower);
+            assert_eq!(card.berry_consumption, berry_consumption);
+        }
+        // Draws keep the order of the old table
+        let commons: Vec<u16> = CARD_DATA
+            .iter()
+            .filter(|(_, rarity, _, _)| *rarity == COMMON)
+            .map(|(id, _, _, _)| *id)
+            .collect();
+        let drawable: Vec<u16> = catalog
+            .drawable_of_rarity(COMMON)
+            .iter()
+            .map(|card| card.id)
+            .collect();
+        assert_eq!(drawable, commons);
+    }
+
+    #[test]
+    fn test_card_catalog_add_and_flags() {
+        let mut catalog = seeded_catalog();
+        let card = CatalogCard {
+            id: 500,
+            rarity: MEGA_RARE,
+            hashpower: 1_000,
+            berry_consumption: 10,
+            enabled: true,
+            booster_eligible: false,
+        };
+        catalog.add(card).unwrap();
+        assert!(catalog.add(card).is_err());
+        assert!(catalog
+            .add(CatalogCard {
+                id: 501,
+                rarity: MEGA_RARE + 1,
+                ..card
+            })
+            .is_err());
+        assert_eq!(catalog.get(500).unwrap().hashpower, 1_000);
+        assert!(catalog
+            .drawable_of_rarity(MEGA_RARE)
+            .iter()
+            .all(|c| c.id != 500));
+
+        catalog.get_mut(500).unwrap().booster_eligible = true;
+        assert!(catalog
+            .drawable_of_rarity(MEGA_RARE)
+            .iter()
+            .any(|c| c.id == 500));
+
+        catalog.get_mut(500).unwrap().enabled = false;
+        assert!(catalog
+            .drawable_of_rarity(MEGA_RARE)
+            .iter()
+            .all(|c| c.id != 500));
+    }
+
+    fn seeded_farm_config() -> FarmConfig {
+        FarmConfig {
+            token_mint: Pubkey::new_unique(),
+            levels: FARM_CONFIGS
+                .iter()
+                .map(|&(total_cards, berry_capacity, cost)| FarmLevel {
+                    total_cards,
+                    berry_capacity,
+                    cost,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_farm_config_set_level() {
+        let mut config = seeded_farm_config();
+        let level = FarmLevel {
+            total_cards: MAX_STAKED_CARDS_PER_PLAYER,
+            berry_capacity: 4_000,
+            cost: 51_200_000_000,
+        };
+
+        // Appends level 11, but cannot skip ahead or touch level 0
+        assert!(config.set_level(12, level).is_err());
+        assert!(config.set_level(0, level).is_err());
+        config.set_level(11, level).unwrap();
+        assert_eq!(config.farm(11).unwrap().berry_capacity, 4_000);
+
+        // Rebalances an existing level, within the staked-card cap
+        config
+            .set_level(
+                2,
+                FarmLevel {
+                    total_cards: 5,
+                    ..level
+                },
+            )
+            .unwrap();
+        assert_eq!(config.farm(2).unwrap().total_cards, 5);
+        assert!(config
+            .set_level(
+                2,
+                FarmLevel {
+                    total_cards: MAX_STAKED_CARDS_PER_PLAYER + 1,
+                    ..level
+                },
+            )
+            .is_err());
+
+        while config.levels.len() < MAX_FARM_LEVELS {
+            let next = config.levels.len() as u8;
+            config.set_level(next, level).unwrap();
+        }
+        assert!(config.set_level(MAX_FARM_LEVELS as u8, level).is_err());
+
+        let mut player = new_player();
+        player.farm.farm_type = 2;
+        player.sync_farm(&config).unwrap();
+        assert_eq!(player.farm.total_cards, 5);
+        assert_eq!(player.farm.berry_capacity, 4_000);
+    }
+
+    #[test]
+    fn test_stake_card_enforces_max_staked_cards() {
+        let mut player = new_player();
+        for _ in 0..=MAX_STAKED_CARDS_PER_PLAYER {
+            player.add_card(Card::default()).unwrap();
+        }
+        for index in 0..MAX_STAKED_CARDS_PER_PLAYER {
+            player.stake_card(index).unwrap();
+        }
+        assert!(player.stake_card(MAX_STAKED_CARDS_PER_PLAYER).is_err());
+    }
+
+    #[test]
+    fn test_card_level_curve_validation() {
+        let mut curve = CardLevel::default_curve();
+        validate_card_level_curve(&curve).unwrap();
+
+        curve[3].xp_required = curve[2].xp_required;
+        assert!(validate_card_level_curve(&curve).is_err());
+
+        // A zero entry ends the curve, so later entries are not checked
+        curve[2].xp_required = 0;
+        validate_card_level_curve(&curve).unwrap();
+
+        curve[0].hashpower_bonus_bps = MAX_CARD_LEVEL_BONUS_BPS + 1;
+        assert!(validate_card_level_curve(&curve).is_err());
+    }
+
+    #[test]
+    fn test_accrue_card_xp_levels_staked_cards() {
+        let curve = CardLevel::default_curve();
+        let mut player = new_player();
+        player.farm.berry_capacity = 5;
+        for _ in 0..2 {
+            player
+                .add_card(Card {
+                    hashpower: 100,
+                    berry_consumption: 2,
+                    ..Card::default()
+                })
+                .unwrap();
+        }
+        player.stake_card(0).unwrap();
+        player.berries = 2;
+        player.total_hashpower = 100;
+
+        // Only staked cards earn XP
+        assert!(player.accrue_card_xp(215_999, &curve).is_empty());
+        assert_eq!(player.accrue_card_xp(1, &curve), vec![0]);
+        assert_eq!(player.cards[0].level, 1);
+        assert_eq!(player.cards[0].hashpower, 110);
+        assert_eq!(player.cards[1].xp, 0);
+        assert_eq!(player.total_hashpower, 110);
+
+        // Several levels at once, compounding the bonus
+        player.accrue_card_xp(3_024_000, &curve);
+        assert_eq!(player.cards[0].level, 4);
+        assert_eq!(player.cards[0].hashpower, 146);
+        assert_eq!(player.total_hashpower, 146);
+
+        // Level 5 adds a berry and waits while the farm has no room for it
+        player.stake_card(1).unwrap();
+        player.berries = 5;
+        player.total_hashpower += 100;
+        player.accrue_card_xp(6_480_000, &curve);
+        assert_eq!(player.cards[0].level, 4);
+        assert_eq!(player.cards[1].level, 4);
+
+        player.farm.berry_capacity = 6;
+        player.accrue_card_xp(1, &curve);
+        assert_eq!(player.cards[0].level, 5);
+        assert_eq!(player.cards[0].berry_consumption, 3);
+        assert_eq!(player.cards[1].level, 4);
+        assert_eq!(player.berries, 6);
+        assert_eq!(
+            player.total_hashpower,
+            player.cards[0].hashpower as u64 + player.cards[1].hashpower as u64
+        );
+    }
+
+    #[test]
+    fn test_global_state_size_matches_layout() {
+        // try_from_slice fails unless every byte is consumed
+        let gs = GlobalState::try_from_slice(&vec![0u8; GlobalState::SIZE - 8]).unwrap();
+        assert_eq!(gs.try_to_vec().unwrap().len(), GlobalState::SIZE - 8);
+        // Space allocated by the launch version of initialize_program
+        assert_eq!(GlobalState::LEGACY_SIZE, 451);
+    }
+
+    #[test]
+    fn test_player_size_matches_layout() {
+        // Set options and the largest pending action fill the reserved space exactly
+        let mut player = new_player();
+        player.referrer = Some(Pubkey::new_unique());
+        player.claim_destination = Some(Pubkey::new_unique());
+        player.claim_keeper = Some(Pubkey::new_unique());
+        player.pending_action = PendingRandomAction::Recycle {
+            card_indices: [0; 128],
+            card_count: 0,
+        };
+        assert_eq!(player.try_to_vec().unwrap().len(), Player::SIZE - 8);
+
+        let legacy = player_to_legacy(&player);
+        assert_eq!(legacy.try_to_vec().unwrap().len(), Player::LEGACY_SIZE - 8);
+    }
+
+    /// The launch-layout image of `player`, as an unmigrated account holds it
+    fn player_to_legacy(player: &Player) -> LegacyPlayer {
+        let mut cards = [LegacyCard::default(); MAX_CARDS_PER_PLAYER as usize];
+        for (legacy, card) in cards.iter_mut().zip(player.cards.iter()) {
+            *legacy = LegacyCard {
+                id: card.id,
+                rarity: card.rarity,
+                hashpower: card.hashpower,
+                berry_consumption: card.berry_consumption,
+            };
+        }
+        LegacyPlayer {
+            owner: player.owner,
+            farm: player.farm.clone(),
+            cards,
+            card_count: player.card_count,
+            staked_cards_bitset: player.staked_cards_bitset,
+            berries: player.berries,
+            total_hashpower: player.total_hashpower,
+            referrer: player.referrer,
+            last_acc_tokens_per_hashpower: player.last_acc_tokens_per_hashpower,
+            last_claim_slot: player.last_claim_slot,
+            last_upgrade_slot: player.last_upgrade_slot,
+            total_rewards: player.total_rewards,
+            total_gambles: player.total_gambles,
+            total_gamble_wins: player.total_gamble_wins,
+            pending_action: player.pending_action.clone(),
+            randomness_account: player.randomness_account,
+            commit_slot: player.commit_slot,
+            total_earnings_for_referrer: player.total_earnings_for_referrer,
+            total_booster_packs_opened: player.total_booster_packs_opened,
+            total_cards_recycled: player.total_cards_recycled,
+            successful_card_recycling: player.successful_card_recycling,
+            total_sol_spent: player.total_sol_spent,
+            total_tokens_spent: player.total_tokens_spent,
+            staked_tokens: player.staked_tokens,
+            last_stake_slot: player.last_stake_slot,
+            last_acc_sol_rewards_per_token: player.last_acc_sol_rewards_per_token,
+            last_acc_token_rewards_per_token: player.last_acc_token_rewards_per_token,
+            claimed_token_rewards: player.claimed_token_rewards,
+            padding: [0; 64],
+        }
+    }
+
+    #[test]
+    fn test_legacy_player_into_player() {
+        let mut player = new_player();
+        for id in 1..=3 {
+            player
+                .add_card(Card {
+                    id,
+                    hashpower: 10 * id,
+                    berry_consumption: 2,
+                    ..Card::default()
+                })
+                .unwrap();
+        }
+        player.stake_card(1).unwrap();
+        player.total_rewards = 1_234;
+        player.pending_action = PendingRandomAction::Gamble { amount: 7 };
+
+        let migrated = player_to_legacy(&player).into_player();
+        assert_eq!(migrated.owner, player.owner);
+        assert_eq!(migrated.card_count, 3);
+        assert_eq!(migrated.cards[2].id, 3);
+        assert_eq!(migrated.cards[2].hashpower, 30);
+        assert!(migrated.is_card_staked(1));
+        assert_eq!(migrated.total_rewards, 1_234);
+        assert!(matches!(
+            migrated.pending_action,
+            PendingRandomAction::Gamble { amount: 7 }
+        ));
+        assert!(migrated.claim_keeper.is_none());
+
+        // Every pre-upgrade card is addressable by serial, and new ones follow on
+        let mut migrated = migrated;
+        assert_eq!(migrated.find_card_by_serial(1).unwrap(), 0);
+        assert_eq!(migrated.find_card_by_serial(3).unwrap(), 2);
+        assert_eq!(migrated.cards[3].serial, 0);
+        assert_eq!(migrated.add_card(Card::default()).unwrap(), 4);
+    }
 }
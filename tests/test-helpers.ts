@@ -72,6 +72,7 @@ export async function setupTestProgram() {
       new BN(0), // startSlot
       new BN("1000000000000000"), // totalSupply (1B with 6 decimals)
       new BN(352733915), // initialRewardRate
+      new BN(47250), // halvingInterval
       null,
       null,
       null,
@@ -91,6 +92,51 @@ export async function setupTestProgram() {
     .signers([authority])
     .rpc();
 
+  await program.methods
+    .initializeEmissionSchedule()
+    .accounts({
+      authority: authority.publicKey,
+      tokenMint: mint,
+    } as any)
+    .signers([authority])
+    .rpc();
+
+  await program.methods
+    .initializeRateHistory()
+    .accounts({
+      authority: authority.publicKey,
+      tokenMint: mint,
+    } as any)
+    .signers([authority])
+    .rpc();
+
+  await program.methods
+    .initializeReferralConfig()
+    .accounts({
+      authority: authority.publicKey,
+      tokenMint: mint,
+    } as any)
+    .signers([authority])
+    .rpc();
+
+  await program.methods
+    .initializeCardCatalog()
+    .accounts({
+      authority: authority.publicKey,
+      tokenMint: mint,
+    } as any)
+    .signers([authority])
+    .rpc();
+
+  await program.methods
+    .initializeFarmConfig()
+    .accounts({
+      authority: authority.publicKey,
+      tokenMint: mint,
+    } as any)
+    .signers([authority])
+    .rpc();
+
   return {
     program,
     provider,